@@ -1,22 +1,48 @@
 use stylish_webrender;
+use std::fs;
+use std::io::Read;
+
+/// The font name styles use for emoji
+const EMOJI_FONT: &str = "Emoji";
+/// The files tried in order for `EMOJI_FONT` as Fira has no
+/// emoji. One placed in `fonts/` is used first, otherwise a
+/// commonly installed one.
+const EMOJI_FILES: &[&str] = &[
+    "fonts/Emoji.ttf",
+    "/usr/share/fonts/truetype/noto/NotoEmoji-Regular.ttf",
+    "/usr/share/fonts/noto/NotoEmoji-Regular.ttf",
+    "/usr/share/fonts/google-noto-emoji/NotoEmoji-Regular.ttf",
+    "/usr/share/fonts/truetype/ancient-scripts/Symbola_hint.ttf",
+    "/usr/share/fonts/TTF/Symbola.ttf",
+    "/usr/share/fonts/gdouros-symbola/Symbola.ttf",
+];
 
 pub struct AssetLoader;
 
 impl stylish_webrender::Assets for AssetLoader {
     fn load_font(&self, name: &str) -> Option<Vec<u8>> {
-        use std::fs;
-        use std::io::Read;
-        let mut f = if let Ok(f) = fs::File::open(format!("fonts/{}.ttf", name)) {
-            f
-        } else {
-            return None;
-        };
-        let mut data = Vec::new();
-        f.read_to_end(&mut data)
-            .ok()
-            .map(|_| data)
+        if name == EMOJI_FONT {
+            if let Some(data) = EMOJI_FILES.iter().filter_map(|v| read(v)).next() {
+                return Some(data);
+            }
+            println!("No emoji font found, add one as fonts/{}.ttf", EMOJI_FONT);
+            return read("fonts/FiraSans-Regular.ttf");
+        }
+        read(&format!("fonts/{}.ttf", name))
     }
     fn load_image(&self, _name: &str) -> Option<stylish_webrender::Image> {
         None
     }
-}
\ No newline at end of file
+}
+
+fn read(path: &str) -> Option<Vec<u8>> {
+    let mut f = if let Ok(f) = fs::File::open(path) {
+        f
+    } else {
+        return None;
+    };
+    let mut data = Vec::new();
+    f.read_to_end(&mut data)
+        .ok()
+        .map(|_| data)
+}
@@ -0,0 +1,414 @@
+//! The connection to the chat server.
+//!
+//! There isn't a real server yet so the other users are
+//! simulated here. Events from the server are queued and
+//! collected each frame like the ui manager's events.
+
+use chrono::prelude::*;
+
+use members::Status;
+
+/// A role as the server describes it
+#[derive(Clone, Debug)]
+pub struct RoleInfo {
+    pub name: String,
+    pub colour: String,
+    pub hoist: bool,
+}
+
+/// A member as the server describes them
+#[derive(Clone, Debug)]
+pub struct MemberInfo {
+    pub name: String,
+    /// The names of the member's roles
+    pub roles: Vec<String>,
+    pub status: Status,
+    pub playing: Option<String>,
+}
+
+/// A message in the server's history
+#[derive(Clone, Debug)]
+pub struct HistoryMessage {
+    pub channel: String,
+    pub author: String,
+    pub time: DateTime<Utc>,
+    pub content: String,
+    pub pinned: bool,
+    /// Each emoji reacted with and the users that did
+    pub reactions: Vec<(String, Vec<String>)>,
+}
+
+/// The state of the server sent when connecting
+#[derive(Clone, Debug)]
+pub struct Ready {
+    /// Roles ordered from highest to lowest
+    pub roles: Vec<RoleInfo>,
+    pub members: Vec<MemberInfo>,
+    /// The history of every channel in the order it
+    /// was sent
+    pub messages: Vec<HistoryMessage>,
+}
+
+/// Something that happened on the server
+#[derive(Clone, Debug)]
+pub enum Event {
+    /// The connection was made and the server's state
+    /// was received
+    Ready(Ready),
+    /// The connection was lost. Requests without a reply
+    /// yet won't get one.
+    Disconnected,
+    /// The server received the message sent with the nonce
+    Sent {
+        nonce: u64,
+    },
+    /// The server refused the message sent with the nonce
+    Rejected {
+        nonce: u64,
+    },
+    /// The user started or is still typing a message in
+    /// the channel
+    Typing {
+        channel: String,
+        user: String,
+    },
+    /// The user connected to a voice channel
+    VoiceJoined {
+        channel: String,
+        user: String,
+    },
+    /// The user started or stopped speaking in voice
+    Speaking {
+        user: String,
+        speaking: bool,
+    },
+    /// A message was sent to a channel
+    Message {
+        channel: String,
+        author: String,
+        content: String,
+    },
+}
+
+/// Something sent to the server by this client
+#[derive(Clone, Debug)]
+pub enum Request {
+    /// This client's user is typing a message in the channel
+    Typing {
+        channel: String,
+    },
+    /// This client's user sent a message. The nonce is
+    /// included in the reply so it can be matched up.
+    Message {
+        nonce: u64,
+        channel: String,
+        content: String,
+    },
+}
+
+/// How long connecting to the simulated server takes, in
+/// frames. Long enough to see the cached state first.
+const CONNECT_TIME: f64 = 60.0 * 2.0;
+/// Time between the simulated connection dropping, in frames
+const OUTAGE_INTERVAL: f64 = 60.0 * 60.0 * 3.0;
+/// How long the simulated connection stays down, in frames
+const OUTAGE_LENGTH: f64 = 60.0 * 20.0;
+/// How long the server takes to reply to a message, in frames
+const SEND_LATENCY: f64 = 60.0 * 0.5;
+/// The most characters the server accepts in a message
+const MAX_MESSAGE_LENGTH: usize = 2000;
+/// Users that the simulation makes type
+const TYPERS: &[(&str, &str)] = &[
+    ("general", "Cldfire"),
+    ("general", "TheUnnamedDude"),
+    ("channel2", "Thinkofname"),
+];
+/// Time between simulated users starting to type, in frames
+const TYPING_INTERVAL: f64 = 60.0 * 30.0;
+/// How long simulated users type for, in frames
+const TYPING_LENGTH: f64 = 60.0 * 12.0;
+/// How often typing events are repeated while typing, in frames
+const TYPING_REPEAT: f64 = 60.0 * 5.0;
+/// Messages the simulation sends to channels other than
+/// the one being viewed, in order
+const MESSAGES: &[(&str, &str, &str)] = &[
+    ("channel2", "Cldfire", "It builds fine for me now"),
+    ("channel3", "Thinkofname", "Anyone around?"),
+    ("channel2", "TheUnnamedDude", "@Rust User can you take a look at this when you have time?"),
+    ("channel3", "Cldfire", "Sort of"),
+];
+/// Time between simulated messages, in frames
+const MESSAGE_INTERVAL: f64 = 60.0 * 45.0;
+/// Users that the simulation puts in voice channels
+const VOICE_USERS: &[(&str, &str)] = &[
+    ("Gaming", "Thinkofname"),
+    ("Gaming", "Cldfire"),
+];
+/// Time between simulated users starting to speak, in frames
+const SPEAKING_INTERVAL: f64 = 60.0 * 3.0;
+/// How long simulated users speak for, in frames
+const SPEAKING_LENGTH: f64 = 60.0 * 1.5;
+
+pub struct Backend {
+    events: Vec<Event>,
+    /// Frames left until connected
+    connecting: f64,
+    /// Frames until the connection drops
+    next_outage: f64,
+    /// Messages the server hasn't replied to with whether
+    /// they will be accepted and the frames left until the
+    /// reply
+    sending: Vec<(u64, bool, f64)>,
+
+    /// Frames until the next simulated user starts typing
+    next_typing: f64,
+    /// Simulated users currently typing and the channel
+    /// they are typing in, with the frames left until they
+    /// stop and until their next event
+    typing: Vec<(String, String, f64, f64)>,
+    round: usize,
+
+    /// Frames until the next simulated message
+    next_message: f64,
+    message: usize,
+
+    /// Frames until the next simulated user speaks
+    next_speaking: f64,
+    /// The simulated user speaking and the frames left until
+    /// they stop
+    speaking: Option<(String, f64)>,
+    speaker: usize,
+}
+
+impl Backend {
+    pub fn new() -> Backend {
+        Backend {
+            events: Vec::new(),
+            connecting: CONNECT_TIME,
+            next_outage: OUTAGE_INTERVAL,
+            sending: Vec::new(),
+
+            next_typing: TYPING_INTERVAL / 3.0,
+            typing: Vec::new(),
+            round: 0,
+
+            next_message: MESSAGE_INTERVAL,
+            message: 0,
+
+            next_speaking: SPEAKING_INTERVAL,
+            speaking: None,
+            speaker: 0,
+        }
+    }
+
+    /// Returns the events received since the last call
+    pub fn events(&mut self) -> ::std::vec::Drain<Event> {
+        self.events.drain(..)
+    }
+
+    /// Sends the request to the server. Requests sent while
+    /// disconnected are lost.
+    pub fn send(&mut self, request: Request) {
+        if !self.is_connected() {
+            return;
+        }
+        match request {
+            // Nobody is around to see it
+            Request::Typing{..} => {},
+            Request::Message{nonce, content, ..} => {
+                let accepted = content.chars().count() <= MAX_MESSAGE_LENGTH;
+                self.sending.push((nonce, accepted, SEND_LATENCY));
+            },
+        }
+    }
+
+    /// Returns whether the connection to the server is made
+    pub fn is_connected(&self) -> bool {
+        self.connecting <= 0.0
+    }
+
+    /// Advances the simulation of the other users
+    pub fn update(&mut self, delta: f64) {
+        if !self.is_connected() {
+            self.connecting -= delta;
+            if self.is_connected() {
+                self.connect();
+            }
+            return;
+        }
+
+        self.next_outage -= delta;
+        if self.next_outage <= 0.0 {
+            self.next_outage += OUTAGE_INTERVAL;
+            self.disconnect();
+            return;
+        }
+
+        for &mut (nonce, accepted, ref mut left) in &mut self.sending {
+            *left -= delta;
+            if *left <= 0.0 {
+                self.events.push(if accepted {
+                    Event::Sent{nonce: nonce}
+                } else {
+                    Event::Rejected{nonce: nonce}
+                });
+            }
+        }
+        self.sending.retain(|&(_, _, left)| left > 0.0);
+
+        self.next_typing -= delta;
+        if self.next_typing <= 0.0 {
+            self.next_typing += TYPING_INTERVAL;
+            // Every third time two people type at once
+            let count = if self.round % 3 == 2 { 2 } else { 1 };
+            for i in 0..count {
+                let (channel, user) = TYPERS[(self.round + i) % TYPERS.len()];
+                self.typing.push((channel.to_owned(), user.to_owned(), TYPING_LENGTH, 0.0));
+            }
+            self.round += 1;
+        }
+
+        self.next_message -= delta;
+        if self.next_message <= 0.0 {
+            self.next_message += MESSAGE_INTERVAL;
+            let (channel, author, content) = MESSAGES[self.message % MESSAGES.len()];
+            self.message += 1;
+            self.events.push(Event::Message {
+                channel: channel.to_owned(),
+                author: author.to_owned(),
+                content: content.to_owned(),
+            });
+        }
+
+        self.update_speaking(delta);
+
+        let events = &mut self.events;
+        self.typing.retain(|&(_, _, left, _)| left > 0.0);
+        for &mut (ref channel, ref user, ref mut left, ref mut repeat) in &mut self.typing {
+            *left -= delta;
+            *repeat -= delta;
+            if *repeat <= 0.0 {
+                *repeat = TYPING_REPEAT;
+                events.push(Event::Typing {
+                    channel: channel.clone(),
+                    user: user.clone(),
+                });
+            }
+        }
+    }
+
+    fn connect(&mut self) {
+        self.events.push(Event::Ready(ready()));
+        // The simulated users are already in voice
+        for &(channel, user) in VOICE_USERS {
+            self.events.push(Event::VoiceJoined {
+                channel: channel.to_owned(),
+                user: user.to_owned(),
+            });
+        }
+    }
+
+    fn disconnect(&mut self) {
+        self.connecting = OUTAGE_LENGTH;
+        self.sending.clear();
+        self.typing.clear();
+        self.events.push(Event::Disconnected);
+    }
+
+    fn update_speaking(&mut self, delta: f64) {
+        let stopped = if let Some((ref user, ref mut left)) = self.speaking {
+            *left -= delta;
+            if *left <= 0.0 { Some(user.clone()) } else { None }
+        } else {
+            None
+        };
+        if let Some(user) = stopped {
+            self.speaking = None;
+            self.events.push(Event::Speaking {
+                user: user,
+                speaking: false,
+            });
+        }
+
+        self.next_speaking -= delta;
+        if self.next_speaking <= 0.0 {
+            self.next_speaking += SPEAKING_INTERVAL;
+            if self.speaking.is_none() {
+                let (_, user) = VOICE_USERS[self.speaker % VOICE_USERS.len()];
+                self.speaker += 1;
+                self.speaking = Some((user.to_owned(), SPEAKING_LENGTH));
+                self.events.push(Event::Speaking {
+                    user: user.to_owned(),
+                    speaking: true,
+                });
+            }
+        }
+    }
+}
+
+/// The simulated server's state
+fn ready() -> Ready {
+    let role = |name: &str, colour: &str, hoist| RoleInfo {
+        name: name.to_owned(),
+        colour: colour.to_owned(),
+        hoist: hoist,
+    };
+    let member = |name: &str, roles: &[&str], status, playing: Option<&str>| MemberInfo {
+        name: name.to_owned(),
+        roles: roles.iter().map(|v| (*v).to_owned()).collect(),
+        status: status,
+        playing: playing.map(|v| v.to_owned()),
+    };
+    let day = Local.ymd(2017, 9, 1);
+    let message = |channel: &str, author: &str, (h, m), content: &str| HistoryMessage {
+        channel: channel.to_owned(),
+        author: author.to_owned(),
+        time: day.and_hms(h, m, 0).with_timezone(&Utc),
+        content: content.to_owned(),
+        pinned: false,
+        reactions: Vec::new(),
+    };
+
+    let mut first = message(
+        "general", "Thinkofname", (13, 37),
+        "This is a test message. It shows the things supported in this dummy client. \
+         Things like **bold** *italic* and ***both.***",
+    );
+    first.pinned = true;
+    first.reactions = vec![
+        ("\u{1F980}".to_owned(), vec!["Cldfire".to_owned(), "Scetch".to_owned()]),
+        ("\u{1F44D}".to_owned(), vec!["TheUnnamedDude".to_owned()]),
+    ];
+
+    Ready {
+        roles: vec![
+            role("Admin", "#1abc9c", true),
+            role("Mod", "#11806a", true),
+            role("Rustacean", "#FFBF00", false),
+        ],
+        members: vec![
+            member("Thinkofname", &["Admin", "Rustacean"], Status::Online, Some("A game")),
+            member("TheUnnamedDude", &["Mod"], Status::Online, None),
+            member("Cldfire", &["Mod"], Status::Idle, Some("Oversalt")),
+            member("Scetch", &["Mod"], Status::Offline, None),
+            member("Rust User", &["Rustacean"], Status::Online, None),
+            member("ferris", &[], Status::Offline, None),
+        ],
+        messages: vec![
+            first,
+            message(
+                "general", "Thinkofname", (13, 38),
+                "Other things. ~~Strikethrough~~ __underlined__ ||spoilers|| `Monospaced code text`",
+            ),
+            message(
+                "general", "Thinkofname", (13, 39),
+                "You can type in the textbox below and thats about it currently. \
+                 In theory you can do pretty much everything with this (the renderer being the main limit), \
+                 you just have to implement the style rules and the event handlers.",
+            ),
+            message("channel2", "Cldfire", (13, 52), "Has anyone tried the new build yet?"),
+            message("channel2", "TheUnnamedDude", (13, 54), "@Rust User you were looking at it earlier right?"),
+            message("channel3", "Thinkofname", (14, 2), "Nothing important here."),
+        ],
+    }
+}
@@ -0,0 +1,133 @@
+//! Collapsible channel categories.
+//!
+//! Every `channels > header` and `channels > channel` has a
+//! `category` property linking them. Collapsing a category
+//! hides its channels apart from the selected one, any with
+//! unread messages and the connected voice channel. Which
+//! categories are collapsed is remembered per guild.
+
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::{Read, Write};
+
+use ui;
+use paths;
+
+const FILE: &str = "collapsed_categories";
+
+pub struct Categories {
+    channels: ui::Node,
+    /// The guild the channel list is showing
+    guild: String,
+    /// The collapsed categories of every guild
+    collapsed: HashMap<String, HashSet<String>>,
+}
+
+impl Categories {
+    pub fn new(root: &ui::Node) -> Categories {
+        let guild = query!(root, server_list > server).next()
+            .and_then(|v| v.get_property::<String>("guild"))
+            .unwrap_or_default();
+        Categories {
+            channels: query!(root, channel_list > channels).next().unwrap(),
+            guild: guild,
+            collapsed: load_collapsed(),
+        }
+    }
+
+    /// Handles a category header being clicked, collapsing
+    /// or expanding the category
+    pub fn toggle(&mut self, header: &ui::Node) {
+        let category = if let Some(category) = header.get_property::<String>("category") {
+            category
+        } else {
+            return;
+        };
+        {
+            let collapsed = self.collapsed.entry(self.guild.clone())
+                .or_insert_with(HashSet::new);
+            if !collapsed.remove(&category) {
+                collapsed.insert(category);
+            }
+        }
+        save_collapsed(&self.collapsed);
+        self.update();
+    }
+
+    /// Updates the chevrons and which channels are hidden.
+    ///
+    /// Called every frame as channels can become selected or
+    /// unread at any point.
+    pub fn update(&self) {
+        let empty = HashSet::new();
+        let collapsed = self.collapsed.get(&self.guild).unwrap_or(&empty);
+        let is_collapsed = |node: &ui::Node| node.get_property::<String>("category")
+            .map_or(false, |v| collapsed.contains(&v));
+
+        for header in query!(self.channels, header) {
+            let closed = is_collapsed(&header);
+            if query!(header, chevron).next().is_none() {
+                header.add_child(node!{
+                    chevron {
+                        @text("")
+                    }
+                });
+            }
+            if let Some(txt) = query!(header, chevron > @text).next() {
+                let chevron = if closed { "\u{25B8}" } else { "\u{25BE}" };
+                if txt.text().map_or(true, |v| v != chevron) {
+                    txt.set_text(chevron);
+                }
+            }
+            set_if_changed(&header, "collapsed", closed);
+        }
+        for channel in query!(self.channels, channel) {
+            let keep = channel.get_property::<bool>("selected").unwrap_or(false)
+                || channel.get_property::<bool>("unread").unwrap_or(false)
+                || channel.get_property::<bool>("connected").unwrap_or(false);
+            set_if_changed(&channel, "collapsed", !keep && is_collapsed(&channel));
+        }
+    }
+}
+
+/// Only sets the property when it differs to avoid relayouts
+/// every frame
+fn set_if_changed(node: &ui::Node, key: &str, value: bool) {
+    if node.get_property::<bool>(key) != Some(value) {
+        node.set_property(key, value);
+    }
+}
+
+/// Loads the collapsed categories, stored as one
+/// `guild<TAB>category` pair per line
+fn load_collapsed() -> HashMap<String, HashSet<String>> {
+    let mut data = String::new();
+    if let Ok(mut f) = fs::File::open(paths::data_dir().join(FILE)) {
+        let _ = f.read_to_string(&mut data);
+    }
+    let mut collapsed = HashMap::new();
+    for line in data.lines() {
+        let mut parts = line.splitn(2, '\t');
+        if let (Some(guild), Some(category)) = (parts.next(), parts.next()) {
+            collapsed.entry(guild.to_owned())
+                .or_insert_with(HashSet::new)
+                .insert(category.to_owned());
+        }
+    }
+    collapsed
+}
+
+fn save_collapsed(collapsed: &HashMap<String, HashSet<String>>) {
+    let res = fs::File::create(paths::data_dir().join(FILE))
+        .and_then(|mut f| {
+            for (guild, categories) in collapsed {
+                for category in categories {
+                    writeln!(f, "{}\t{}", guild, category)?;
+                }
+            }
+            Ok(())
+        });
+    if let Err(err) = res {
+        println!("Failed to save collapsed categories: {}", err);
+    }
+}
@@ -0,0 +1,242 @@
+//! The bar above the chat.
+//!
+//! Shows the name and topic of the channel being viewed and
+//! has the pinned messages popout, the member list toggle and
+//! the search field. Search results are listed in
+//! `search_panel` at the side of the chat.
+
+use std::cmp;
+use chrono::prelude::*;
+
+use ui;
+use chat::{Chat, Message};
+use chat::search::Query;
+use chat::time::TimeFormat;
+
+const POPOUT_WIDTH: i32 = 420;
+/// The most search results listed
+const MAX_RESULTS: usize = 25;
+/// The most characters of a message shown in the popouts
+const PREVIEW_LENGTH: usize = 80;
+
+pub struct ChannelBar {
+    name: ui::Node,
+    topic: ui::Node,
+    search: ui::Node,
+    panel: ui::Node,
+    channels: ui::Node,
+    /// The nodes whose layout depends on whether the member
+    /// list is shown
+    layout: Vec<ui::Node>,
+    /// The channel the name and topic are showing
+    channel: Option<String>,
+    members_shown: bool,
+    popout: Option<ui::Node>,
+    format: TimeFormat,
+}
+
+impl ChannelBar {
+    pub fn new(root: &ui::Node) -> ChannelBar {
+        let bar = query!(root, channel_bar).next().unwrap();
+        ChannelBar {
+            name: query!(bar, name > @text).next().unwrap(),
+            topic: query!(bar, topic > @text).next().unwrap(),
+            search: query!(bar, search).next().unwrap(),
+            panel: query!(root, search_panel).next().unwrap(),
+            channels: query!(root, channel_list > channels).next().unwrap(),
+            layout: vec![
+                bar.clone(),
+                query!(root, chat_area).next().unwrap(),
+                query!(root, message_box).next().unwrap(),
+                query!(root, user_list).next().unwrap(),
+            ],
+            channel: None,
+            members_shown: true,
+            popout: None,
+            format: TimeFormat::default(),
+        }
+    }
+
+    /// Changes how the times of search results are displayed
+    pub fn set_format(&mut self, format: TimeFormat) {
+        self.format = format;
+    }
+
+    /// Updates the name and topic when the channel being
+    /// viewed changes
+    pub fn update(&mut self, chat: &Chat) {
+        if self.channel.as_ref().map_or(false, |v| v == chat.channel()) {
+            return;
+        }
+        let channel = chat.channel().to_owned();
+        let topic = query!(self.channels, channel)
+            .find(|v| v.get_property::<String>("name").map_or(false, |v| v == channel))
+            .and_then(|v| v.get_property::<String>("topic"))
+            .unwrap_or_default();
+        // Direct messages are named after the user already
+        let name = if channel.starts_with('@') {
+            channel.clone()
+        } else {
+            format!("# {}", channel)
+        };
+        self.name.set_text(name);
+        self.topic.set_text(topic);
+        self.channel = Some(channel);
+    }
+
+    /// Shows or hides `user_list`, giving its space to the
+    /// chat when hidden
+    pub fn toggle_members(&mut self) {
+        self.members_shown = !self.members_shown;
+        for node in &self.layout {
+            node.set_property("members", self.members_shown);
+        }
+    }
+
+    /// Opens the list of pinned messages below the button
+    pub fn open_pins(&mut self, manager: &mut ui::Manager, chat: &Chat, anchor: &ui::Node) {
+        let pins = chat.pinned_messages();
+        let title = "PINNED MESSAGES".to_owned();
+        self.open_popout(manager, anchor, title, &pins, "This channel doesn't have any pinned messages");
+    }
+
+    /// Searches every channel for the text in the search field
+    /// and lists the matches newest first in the side panel
+    pub fn search(&mut self, chat: &Chat) {
+        let text = query!(self.search, content > @text).next()
+            .and_then(|v| v.text())
+            .unwrap_or_default();
+        let search = Query::parse(&text);
+        if search.is_empty() {
+            self.close_search();
+            return;
+        }
+        let results = chat.search(&search);
+
+        let results_node = query!(self.panel, results).next().unwrap();
+        for item in query!(results_node, result).collect::<Vec<_>>() {
+            results_node.remove_child(item);
+        }
+        if let Some(empty) = query!(results_node, empty).next() {
+            results_node.remove_child(empty);
+        }
+        let title = match results.len() {
+            1 => "1 RESULT".to_owned(),
+            n if n > MAX_RESULTS => format!("{} RESULTS, SHOWING THE NEWEST {}", n, MAX_RESULTS),
+            n => format!("{} RESULTS", n),
+        };
+        if let Some(txt) = query!(self.panel, header > @text).next() {
+            txt.set_text(title);
+        }
+        if results.is_empty() {
+            results_node.add_child(node!{
+                empty {
+                    @text("No messages matched your search")
+                }
+            });
+        }
+        let today = Local::today();
+        for msg in results.into_iter().take(MAX_RESULTS).filter_map(|id| chat.message(id)) {
+            let item = node!{
+                result {
+                    author {
+                        @text(msg.author.clone())
+                    }
+                    location {
+                        @text(format!("#{} \u{2014} {}", msg.channel, self.format.timestamp(msg.time, today)))
+                    }
+                    preview {
+                        @text(preview(&msg.content))
+                    }
+                }
+            };
+            item.set_property("msg_id", msg.id as i32);
+            item.set_property("col", msg.colour.clone());
+            results_node.add_child(item);
+        }
+        self.set_searching(true);
+    }
+
+    /// Hides the search results
+    pub fn close_search(&mut self) {
+        self.set_searching(false);
+    }
+
+    fn set_searching(&self, searching: bool) {
+        self.panel.set_property("open", searching);
+        for node in &self.layout {
+            node.set_property("search", searching);
+        }
+    }
+
+    fn open_popout(
+        &mut self, manager: &mut ui::Manager,
+        anchor: &ui::Node, title: String,
+        messages: &[&Message], empty: &str,
+    ) {
+        self.close_popout(manager);
+        let rect = if let Some(rect) = anchor.render_position() {
+            rect
+        } else {
+            return;
+        };
+        let node = node!{
+            message_popout {
+                title {
+                    @text(title)
+                }
+            }
+        };
+        if messages.is_empty() {
+            node.add_child(node!{
+                empty {
+                    @text(empty)
+                }
+            });
+        }
+        for msg in messages {
+            let item = node!{
+                item {
+                    author {
+                        @text(msg.author.clone())
+                    }
+                    preview {
+                        @text(preview(&msg.content))
+                    }
+                }
+            };
+            item.set_property("msg_id", msg.id as i32);
+            item.set_property("col", msg.colour.clone());
+            node.add_child(item);
+        }
+        let (width, _) = manager.window_size();
+        let x = cmp::min(rect.x + rect.width - POPOUT_WIDTH, width - POPOUT_WIDTH);
+        node.set_property("px", cmp::max(0, x));
+        node.set_property("py", rect.y + rect.height + 8);
+        manager.push_overlay(node.clone(), true);
+        self.popout = Some(node);
+    }
+
+    pub fn close_popout(&mut self, manager: &mut ui::Manager) {
+        if let Some(node) = self.popout.take() {
+            manager.remove_overlay(&node);
+        }
+    }
+
+    /// Called when the manager dismissed the popout's overlay
+    pub fn popout_dismissed(&mut self) {
+        self.popout = None;
+    }
+}
+
+/// Returns the start of the message's first line
+fn preview(content: &str) -> String {
+    let line = content.lines().next().unwrap_or("");
+    if line.chars().count() > PREVIEW_LENGTH || content.lines().nth(1).is_some() {
+        let mut text = line.chars().take(PREVIEW_LENGTH).collect::<String>();
+        text.push('\u{2026}');
+        text
+    } else {
+        line.to_owned()
+    }
+}
@@ -0,0 +1,710 @@
+//! The chat model.
+//!
+//! Messages are stored here and the UI is updated from the
+//! changes reported by the model instead of by editing the
+//! message nodes directly.
+
+pub mod render;
+pub mod time;
+pub mod search;
+
+use std::cmp;
+use std::mem;
+use std::collections::{HashMap, HashSet};
+use chrono::prelude::*;
+use chrono::Duration;
+
+/// Unique id of a message
+pub type MessageId = u64;
+/// Identifies a message in the server's history, which doesn't
+/// share this client's ids, by its channel, author and time
+pub type MessageKey = (String, String, DateTime<Utc>);
+
+/// How long after a message follow up messages from the same
+/// author are grouped with it, in minutes
+pub const GROUP_TIMEOUT: i64 = 7;
+
+/// How long a user is shown as typing after their last
+/// typing notification, in frames
+pub const TYPING_TIMEOUT: f64 = 60.0 * 10.0;
+/// The minimum time between typing notifications sent for
+/// this client's user, in frames
+pub const TYPING_THROTTLE: f64 = 60.0 * 8.0;
+
+/// A single chat message
+#[derive(Clone, Debug)]
+pub struct Message {
+    pub id: MessageId,
+    /// The name of the channel the message was sent in
+    pub channel: String,
+    pub author: String,
+    /// The colour of the author's name
+    pub colour: String,
+    pub time: DateTime<Utc>,
+    /// The message's text, formatted as markdown
+    pub content: String,
+    /// When the message was last edited if ever
+    pub edited: Option<DateTime<Utc>>,
+    /// The message this is a reply to
+    pub reply_to: Option<MessageId>,
+    /// Reactions in the order they were first added
+    pub reactions: Vec<Reaction>,
+    /// Whether the message is pinned to its channel
+    pub pinned: bool,
+    /// Whether the server has received the message
+    pub state: SendState,
+}
+
+/// How far sending a message by this client's user got
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SendState {
+    /// The server has the message
+    Sent,
+    /// The message is waiting in the outbox
+    Sending,
+    /// The server rejected the message
+    Failed,
+}
+
+/// An emoji reaction to a message
+#[derive(Clone, Debug)]
+pub struct Reaction {
+    pub emoji: String,
+    /// The users that reacted with this emoji
+    pub users: Vec<String>,
+}
+
+impl Message {
+    /// Returns whether this message continues the group
+    /// that `prev` is part of
+    pub fn follows(&self, prev: &Message) -> bool {
+        // Replies always start a new group so the quote
+        // has somewhere to go
+        self.reply_to.is_none()
+            && prev.author == self.author
+            && prev.local_date() == self.local_date()
+            && self.time.signed_duration_since(prev.time) < Duration::minutes(GROUP_TIMEOUT)
+    }
+
+    /// The day the message was sent on in local time
+    pub fn local_date(&self) -> Date<Local> {
+        self.time.with_timezone(&Local).date()
+    }
+
+    pub fn key(&self) -> MessageKey {
+        (self.channel.clone(), self.author.clone(), self.time)
+    }
+}
+
+/// A change to the model that the UI needs to reflect
+#[derive(Clone, Copy, Debug)]
+pub enum Change {
+    /// A new message was added to the end of the history
+    Added(MessageId),
+    /// The content of the message changed
+    Edited(MessageId),
+    /// The message was removed from the history
+    Deleted(MessageId),
+    /// The message being edited inline changed
+    Editing(Option<MessageId>),
+    /// The message being replied to changed
+    Replying(Option<MessageId>),
+    /// The reactions on the message changed
+    Reactions(MessageId),
+    /// The message was pinned or unpinned
+    Pinned(MessageId),
+    /// The message was sent or failed to send
+    State(MessageId),
+    /// The users that are typing changed
+    Typing,
+    /// A different channel is being viewed, the position
+    /// of its new messages divider changed or the history
+    /// was reordered
+    Channel,
+    /// The unread state of the channel changed
+    Unread(String),
+}
+
+/// The messages and state of the chat
+pub struct Chat {
+    /// Messages from every channel in the order they were sent
+    messages: Vec<Message>,
+    /// The channel being viewed
+    channel: String,
+    /// The last message read in each channel
+    last_read: HashMap<String, MessageId>,
+    /// Channels that don't show as unread
+    muted: HashSet<String>,
+    /// Messages from the server that were deleted here so
+    /// reconciling doesn't add them back
+    deleted: HashSet<MessageKey>,
+    index: search::Index,
+    /// Set when something that is cached changes
+    modified: bool,
+    /// The first message that was unread when the current
+    /// channel was opened
+    new_since: Option<MessageId>,
+    next_id: MessageId,
+    changes: Vec<Change>,
+    editing: Option<MessageId>,
+    replying: Option<MessageId>,
+    /// Users typing in the order they started with the
+    /// channel they are typing in and the frames left until
+    /// they time out
+    typing: Vec<(String, String, f64)>,
+    /// Frames until another typing notification can be sent
+    typing_cooldown: f64,
+
+    /// The name messages sent by this client are sent as
+    pub user: String,
+    /// The colour of this client's user name
+    pub colour: String,
+}
+
+impl Chat {
+    pub fn new(user: &str, colour: &str, channel: &str) -> Chat {
+        Chat {
+            messages: Vec::new(),
+            channel: channel.to_owned(),
+            last_read: HashMap::new(),
+            muted: HashSet::new(),
+            deleted: HashSet::new(),
+            index: search::Index::new(),
+            modified: false,
+            new_since: None,
+            next_id: 0,
+            changes: Vec::new(),
+            editing: None,
+            replying: None,
+            typing: Vec::new(),
+            typing_cooldown: 0.0,
+
+            user: user.to_owned(),
+            colour: colour.to_owned(),
+        }
+    }
+
+    /// Returns the changes since the last call
+    pub fn changes(&mut self) -> ::std::vec::Drain<Change> {
+        let modified = self.changes.iter().any(|v| match *v {
+            Change::Editing(_) | Change::Replying(_) | Change::Typing | Change::Channel => false,
+            _ => true,
+        });
+        self.modified |= modified;
+        self.changes.drain(..)
+    }
+
+    /// Returns whether anything that is cached changed since
+    /// the last call
+    pub fn take_modified(&mut self) -> bool {
+        mem::replace(&mut self.modified, false)
+    }
+
+    /// Messages from every channel in the order they were sent
+    pub fn messages(&self) -> &[Message] {
+        &self.messages
+    }
+
+    /// Replaces the history with messages loaded from the
+    /// cache, keeping their ids
+    pub fn restore(
+        &mut self,
+        messages: Vec<Message>,
+        last_read: HashMap<String, MessageId>,
+        deleted: HashSet<MessageKey>,
+    ) {
+        self.index = search::Index::new();
+        for msg in &messages {
+            self.index.insert(msg);
+            self.next_id = cmp::max(self.next_id, msg.id + 1);
+        }
+        self.messages = messages;
+        self.last_read = last_read;
+        self.deleted = deleted;
+        let channel = self.channel.clone();
+        self.mark_read(&channel);
+        // Rebuilds the view and the unread states
+        self.changes.push(Change::Channel);
+    }
+
+    /// The last message read in each channel
+    pub fn last_read(&self) -> &HashMap<String, MessageId> {
+        &self.last_read
+    }
+
+    /// Messages from the server that were deleted here
+    pub fn deleted(&self) -> &HashSet<MessageKey> {
+        &self.deleted
+    }
+
+    /// Forgets deleted messages the server no longer has as
+    /// they can't come back
+    pub fn retain_deleted(&mut self, server: &HashSet<MessageKey>) {
+        self.deleted.retain(|v| server.contains(v));
+    }
+
+    /// Puts the history back in the order the messages were
+    /// sent after older messages were added to the end
+    pub fn sort_by_time(&mut self) {
+        let sorted = self.messages.windows(2).all(|v| v[0].time <= v[1].time);
+        if !sorted {
+            // Stable so messages sent at the same time keep
+            // their order
+            self.messages.sort_by_key(|v| v.time);
+            self.changes.push(Change::Channel);
+        }
+    }
+
+    /// The messages in the channel being viewed in the
+    /// order they were sent
+    pub fn channel_messages(&self) -> Vec<&Message> {
+        self.messages.iter()
+            .filter(|v| v.channel == self.channel)
+            .collect()
+    }
+
+    /// The name of the channel being viewed
+    pub fn channel(&self) -> &str {
+        &self.channel
+    }
+
+    /// Switches to viewing the channel, marking it as read
+    pub fn set_channel(&mut self, channel: &str) {
+        if self.channel == channel {
+            return;
+        }
+        self.cancel_edit();
+        self.cancel_reply();
+        self.channel = channel.to_owned();
+        self.new_since = self.unread_messages(channel).next().map(|v| v.id);
+        self.mark_read(channel);
+        self.changes.push(Change::Channel);
+    }
+
+    /// The message the "New messages" divider is shown above
+    pub fn new_since(&self) -> Option<MessageId> {
+        self.new_since
+    }
+
+    fn unread_messages<'a>(&'a self, channel: &'a str) -> Box<Iterator<Item=&'a Message> + 'a> {
+        let last_read = self.last_read.get(channel).cloned();
+        Box::new(self.messages.iter()
+            .filter(move |v| v.channel == channel
+                && last_read.map_or(true, |l| v.id > l)
+                && v.author != self.user))
+    }
+
+    /// Returns whether the channel has messages that haven't
+    /// been read
+    pub fn is_unread(&self, channel: &str) -> bool {
+        self.unread_messages(channel).next().is_some()
+    }
+
+    /// The number of unread messages in the channel that
+    /// mention this client's user
+    pub fn mentions(&self, channel: &str) -> usize {
+        let mention = format!("@{}", self.user);
+        self.unread_messages(channel)
+            .filter(|v| v.content.contains(&mention))
+            .count()
+    }
+
+    /// Returns whether the channel's unread messages and
+    /// mentions are hidden
+    pub fn is_muted(&self, channel: &str) -> bool {
+        self.muted.contains(channel)
+    }
+
+    /// The channels that are muted
+    pub fn muted(&self) -> &HashSet<String> {
+        &self.muted
+    }
+
+    /// Mutes or unmutes the channel
+    pub fn set_muted(&mut self, channel: &str, muted: bool) -> bool {
+        let changed = if muted {
+            self.muted.insert(channel.to_owned())
+        } else {
+            self.muted.remove(channel)
+        };
+        if changed {
+            self.changes.push(Change::Unread(channel.to_owned()));
+        }
+        changed
+    }
+
+    /// Marks every message in the channel as read
+    pub fn mark_read(&mut self, channel: &str) {
+        // Messages added while reconciling can be older than
+        // ones with lower ids so the newest id is used instead
+        // of the last message
+        let last = self.messages.iter()
+            .filter(|v| v.channel == channel)
+            .map(|v| v.id)
+            .max();
+        if let Some(last) = last {
+            if self.last_read.insert(channel.to_owned(), last) != Some(last) {
+                self.changes.push(Change::Unread(channel.to_owned()));
+            }
+        }
+    }
+
+    /// Returns the position of the message in the history
+    pub fn index_of(&self, id: MessageId) -> Option<usize> {
+        self.messages.iter().position(|v| v.id == id)
+    }
+
+    /// Returns the message with the given id
+    pub fn message(&self, id: MessageId) -> Option<&Message> {
+        self.messages.iter().find(|v| v.id == id)
+    }
+
+    /// Adds a message to the end of the channel's history
+    pub fn add_message(
+        &mut self,
+        channel: &str,
+        author: &str, colour: &str,
+        time: DateTime<Utc>,
+        content: &str,
+    ) -> MessageId {
+        self.push_message(channel, author, colour, time, content, None)
+    }
+
+    fn push_message(
+        &mut self,
+        channel: &str,
+        author: &str, colour: &str,
+        time: DateTime<Utc>,
+        content: &str,
+        reply_to: Option<MessageId>,
+    ) -> MessageId {
+        let id = self.next_id;
+        self.next_id += 1;
+        // Sending a message ends typing
+        if author == self.user {
+            self.typing_cooldown = 0.0;
+        } else {
+            self.stop_typing(channel, author);
+        }
+        self.messages.push(Message {
+            id: id,
+            channel: channel.to_owned(),
+            author: author.to_owned(),
+            colour: colour.to_owned(),
+            time: time,
+            content: content.to_owned(),
+            edited: None,
+            reply_to: reply_to,
+            reactions: Vec::new(),
+            pinned: false,
+            state: SendState::Sent,
+        });
+        if let Some(msg) = self.messages.last() {
+            self.index.insert(msg);
+        }
+        self.changes.push(Change::Added(id));
+        if channel == self.channel {
+            // The channel being viewed is read as messages arrive
+            self.mark_read(channel);
+        } else {
+            self.changes.push(Change::Unread(channel.to_owned()));
+        }
+        id
+    }
+
+    /// Sends a message to the current channel as this client's
+    /// user, replying to the message selected with `start_reply`
+    /// if any.
+    ///
+    /// The message starts out as `Sending` until the outbox
+    /// hears back from the server.
+    pub fn send(&mut self, content: &str) -> MessageId {
+        let (user, colour) = (self.user.clone(), self.colour.clone());
+        let channel = self.channel.clone();
+        let reply_to = self.replying;
+        self.cancel_reply();
+        // Sending means the new messages have been seen
+        if self.new_since.take().is_some() {
+            self.changes.push(Change::Channel);
+        }
+        let id = self.push_message(&channel, &user, &colour, Utc::now(), content, reply_to);
+        self.set_new_state(SendState::Sending);
+        id
+    }
+
+    /// Adds a message this client's user sent that the server
+    /// hasn't received yet, used to restore the outbox
+    pub fn add_unsent(
+        &mut self,
+        channel: &str,
+        time: DateTime<Utc>,
+        content: &str,
+        reply_to: Option<MessageId>,
+        state: SendState,
+    ) -> MessageId {
+        let (user, colour) = (self.user.clone(), self.colour.clone());
+        let id = self.push_message(channel, &user, &colour, time, content, reply_to);
+        self.set_new_state(state);
+        id
+    }
+
+    /// Sets the state of the message just added. The view
+    /// hasn't seen the message yet so no change is needed.
+    fn set_new_state(&mut self, state: SendState) {
+        if let Some(msg) = self.messages.last_mut() {
+            msg.state = state;
+        }
+    }
+
+    /// Changes how far sending the message got
+    pub fn set_state(&mut self, id: MessageId, state: SendState) -> bool {
+        let changed = self.messages.iter_mut()
+            .find(|v| v.id == id)
+            .map_or(false, |v| if v.state == state {
+                false
+            } else {
+                v.state = state;
+                true
+            });
+        if changed {
+            self.changes.push(Change::State(id));
+        }
+        changed
+    }
+
+    /// Makes the next sent message a reply to this one
+    pub fn start_reply(&mut self, id: MessageId) -> bool {
+        if self.message(id).is_none() {
+            return false;
+        }
+        if self.replying != Some(id) {
+            self.replying = Some(id);
+            self.changes.push(Change::Replying(Some(id)));
+        }
+        true
+    }
+
+    /// Stops replying to a message
+    pub fn cancel_reply(&mut self) {
+        if self.replying.take().is_some() {
+            self.changes.push(Change::Replying(None));
+        }
+    }
+
+    /// The other users currently typing in the channel being
+    /// viewed in the order they started
+    pub fn typing(&self) -> Vec<&str> {
+        self.typing.iter()
+            .filter(|v| v.0 == self.channel)
+            .map(|v| v.1.as_str())
+            .collect()
+    }
+
+    /// Marks the user as typing in the channel, called for
+    /// each typing notification received
+    pub fn user_typing(&mut self, channel: &str, user: &str) {
+        if user == self.user {
+            return;
+        }
+        if let Some(typing) = self.typing.iter_mut().find(|v| v.0 == channel && v.1 == user) {
+            typing.2 = TYPING_TIMEOUT;
+            return;
+        }
+        self.typing.push((channel.to_owned(), user.to_owned(), TYPING_TIMEOUT));
+        self.changes.push(Change::Typing);
+    }
+
+    /// Stops showing the user as typing in the channel
+    pub fn stop_typing(&mut self, channel: &str, user: &str) {
+        let len = self.typing.len();
+        self.typing.retain(|v| v.0 != channel || v.1 != user);
+        if self.typing.len() != len {
+            self.changes.push(Change::Typing);
+        }
+    }
+
+    /// Times out users that stopped sending typing
+    /// notifications
+    pub fn update_typing(&mut self, delta: f64) {
+        self.typing_cooldown -= delta;
+        let len = self.typing.len();
+        for typing in &mut self.typing {
+            typing.2 -= delta;
+        }
+        self.typing.retain(|v| v.2 > 0.0);
+        if self.typing.len() != len {
+            self.changes.push(Change::Typing);
+        }
+    }
+
+    /// Called when this client's user types in the message box.
+    ///
+    /// Returns whether a typing notification should be sent,
+    /// limited to one every `TYPING_THROTTLE` frames.
+    pub fn typed(&mut self) -> bool {
+        if self.typing_cooldown > 0.0 {
+            false
+        } else {
+            self.typing_cooldown = TYPING_THROTTLE;
+            true
+        }
+    }
+
+    /// Returns whether the message was sent by this client's user
+    pub fn is_own(&self, msg: &Message) -> bool {
+        msg.author == self.user
+    }
+
+    /// The message currently being edited inline
+    pub fn editing(&self) -> Option<MessageId> {
+        self.editing
+    }
+
+    /// Returns whether the message was sent by this client's
+    /// user and the server has it, which editing needs
+    fn is_editable(&self, msg: &Message) -> bool {
+        self.is_own(msg) && msg.state == SendState::Sent
+    }
+
+    /// Starts editing the message if it can be edited
+    pub fn start_edit(&mut self, id: MessageId) -> bool {
+        let editable = self.message(id).map_or(false, |v| self.is_editable(v));
+        if editable && self.editing != Some(id) {
+            self.editing = Some(id);
+            self.changes.push(Change::Editing(Some(id)));
+        }
+        editable
+    }
+
+    /// Starts editing the last message this client's user sent
+    /// that can be edited
+    pub fn start_edit_last(&mut self) -> bool {
+        let last = self.messages.iter()
+            .rev()
+            .find(|v| v.channel == self.channel && self.is_editable(v))
+            .map(|v| v.id);
+        last.map_or(false, |id| self.start_edit(id))
+    }
+
+    /// Stops editing without changing the message
+    pub fn cancel_edit(&mut self) {
+        if self.editing.take().is_some() {
+            self.changes.push(Change::Editing(None));
+        }
+    }
+
+    /// Replaces the content of the message and stops editing it.
+    ///
+    /// Empty or unchanged content leaves the message as it was.
+    pub fn edit(&mut self, id: MessageId, content: &str) -> bool {
+        if self.editing == Some(id) {
+            self.cancel_edit();
+        }
+        if !self.message(id).map_or(false, |v| self.is_editable(v)) {
+            return false;
+        }
+        let msg = if let Some(msg) = self.messages.iter_mut().find(|v| v.id == id) {
+            msg
+        } else {
+            return false;
+        };
+        if content.trim().is_empty() || msg.content == content {
+            return false;
+        }
+        msg.content = content.to_owned();
+        msg.edited = Some(Utc::now());
+        self.index.insert(msg);
+        self.changes.push(Change::Edited(id));
+        true
+    }
+
+    /// Adds or removes this client's user's reaction
+    pub fn toggle_reaction(&mut self, id: MessageId, emoji: &str) -> bool {
+        let user = self.user.clone();
+        self.toggle_reaction_as(id, emoji, &user)
+    }
+
+    /// Adds the user's reaction to the message, or removes
+    /// it if they already reacted with the emoji
+    pub fn toggle_reaction_as(&mut self, id: MessageId, emoji: &str, user: &str) -> bool {
+        let msg = if let Some(msg) = self.messages.iter_mut().find(|v| v.id == id) {
+            msg
+        } else {
+            return false;
+        };
+        let pos = msg.reactions.iter().position(|v| v.emoji == emoji);
+        if let Some(pos) = pos {
+            let empty = {
+                let reaction = &mut msg.reactions[pos];
+                if let Some(idx) = reaction.users.iter().position(|v| v == user) {
+                    reaction.users.remove(idx);
+                } else {
+                    reaction.users.push(user.to_owned());
+                }
+                reaction.users.is_empty()
+            };
+            if empty {
+                msg.reactions.remove(pos);
+            }
+        } else {
+            msg.reactions.push(Reaction {
+                emoji: emoji.to_owned(),
+                users: vec![user.to_owned()],
+            });
+        }
+        self.changes.push(Change::Reactions(id));
+        true
+    }
+
+    /// Removes the message from the history
+    pub fn delete(&mut self, id: MessageId) -> bool {
+        let idx = if let Some(idx) = self.index_of(id) {
+            idx
+        } else {
+            return false;
+        };
+        if self.editing == Some(id) {
+            self.cancel_edit();
+        }
+        if self.replying == Some(id) {
+            self.cancel_reply();
+        }
+        let msg = self.messages.remove(idx);
+        if msg.state == SendState::Sent {
+            self.deleted.insert(msg.key());
+        }
+        self.index.remove(id);
+        self.changes.push(Change::Deleted(id));
+        true
+    }
+
+    /// Returns the messages matching the search, newest first
+    pub fn search(&self, query: &search::Query) -> Vec<MessageId> {
+        self.index.search(query)
+    }
+
+    /// Pins or unpins the message
+    pub fn set_pinned(&mut self, id: MessageId, pinned: bool) -> bool {
+        let changed = self.messages.iter_mut()
+            .find(|v| v.id == id)
+            .map_or(false, |v| if v.pinned == pinned {
+                false
+            } else {
+                v.pinned = pinned;
+                true
+            });
+        if changed {
+            self.changes.push(Change::Pinned(id));
+        }
+        changed
+    }
+
+    /// The pinned messages in the channel being viewed,
+    /// newest first
+    pub fn pinned_messages(&self) -> Vec<&Message> {
+        self.messages.iter()
+            .rev()
+            .filter(|v| v.channel == self.channel && v.pinned)
+            .collect()
+    }
+}
@@ -0,0 +1,570 @@
+//! Builds the nodes in `chat_area` from the chat model.
+
+use std::mem;
+use std::collections::HashMap;
+use chrono::prelude::*;
+
+use ui;
+use markdown;
+use emoji;
+use super::{Chat, Change, Message, MessageId, Reaction, SendState};
+use super::time::TimeFormat;
+
+/// How long a message stays highlighted after jumping to
+/// it, in frames
+pub const HIGHLIGHT_TIME: f64 = 120.0;
+
+/// Keeps the nodes in `chat_area > content` in sync
+/// with the chat model
+pub struct ChatView {
+    area: ui::Node,
+    content: ui::Node,
+    reply_bar: ui::Node,
+    typing: ui::Node,
+    channels: ui::Node,
+    server: ui::Node,
+    nodes: HashMap<MessageId, ui::Node>,
+    /// Every node added to `content`, including dividers
+    children: Vec<ui::Node>,
+
+    /// The message the quick reaction picker is open for
+    picker: Option<MessageId>,
+    /// The message shown with the inline edit box
+    editing: Option<MessageId>,
+    /// Messages whose content needs recreating
+    refresh: Vec<MessageId>,
+    /// The message to scroll to once it has been laid out
+    jump: Option<MessageId>,
+
+    format: TimeFormat,
+    /// The users that `@` mentions can refer to
+    names: Vec<String>,
+    /// The day the current labels were created relative to
+    today: Date<Local>,
+    dirty: bool,
+}
+
+impl ChatView {
+    pub fn new(root: &ui::Node) -> ChatView {
+        ChatView {
+            area: query!(root, chat_area).next().unwrap(),
+            content: query!(root, chat_area > content).next().unwrap(),
+            reply_bar: query!(root, message_box > reply_bar).next().unwrap(),
+            typing: query!(root, message_box > typing).next().unwrap(),
+            channels: query!(root, channel_list > channels).next().unwrap(),
+            server: query!(root, server_list > server).next().unwrap(),
+            nodes: HashMap::new(),
+            children: Vec::new(),
+
+            picker: None,
+            editing: None,
+            refresh: Vec::new(),
+            jump: None,
+
+            format: TimeFormat::default(),
+            names: Vec::new(),
+            today: Local::today(),
+            dirty: false,
+        }
+    }
+
+    /// Changes how timestamps are displayed
+    pub fn set_format(&mut self, format: TimeFormat) {
+        if self.format != format {
+            self.format = format;
+            self.dirty = true;
+        }
+    }
+
+    /// Sets the users that mentions in messages are linked to
+    pub fn set_names(&mut self, names: Vec<String>) {
+        if self.names != names {
+            self.names = names;
+            self.dirty = true;
+        }
+    }
+
+    /// Applies the changes made to the chat since
+    /// the last update.
+    ///
+    /// Returns the inline edit box if editing started so
+    /// that it can be focused.
+    pub fn update(&mut self, chat: &mut Chat) -> Option<ui::Node> {
+        let changes = chat.changes().collect::<Vec<_>>();
+        let mut focus = false;
+        for change in &changes {
+            match *change {
+                Change::Added(_) => {},
+                // Grouping depends on the surrounding messages so
+                // its simpler to recreate everything
+                Change::Deleted(_) => self.dirty = true,
+                Change::Edited(id) => {
+                    self.refresh.push(id);
+                    // Replies quote the start of the message
+                    self.refresh.extend(chat.channel_messages().iter()
+                        .filter(|v| v.reply_to == Some(id))
+                        .map(|v| v.id));
+                },
+                Change::Reactions(id) | Change::State(id) => self.refresh.push(id),
+                Change::Editing(id) => {
+                    self.refresh.extend(self.editing);
+                    self.refresh.extend(id);
+                    self.editing = id;
+                    focus = id.is_some();
+                },
+                Change::Replying(id) => self.update_reply_bar(chat, id),
+                Change::Typing => self.update_typing(chat),
+                // Only shown in the pins popout
+                Change::Pinned(_) => {},
+                Change::Channel => {
+                    self.dirty = true;
+                    self.area.set_property("scroll", 0);
+                    self.update_unread(chat);
+                    // Only users typing in the new channel are shown
+                    self.update_typing(chat);
+                },
+                Change::Unread(_) => self.update_unread(chat),
+            }
+        }
+
+        // "Today" becomes "Yesterday" at midnight so every
+        // label needs recreating
+        let today = Local::today();
+        if today != self.today {
+            self.today = today;
+            self.dirty = true;
+        }
+        if self.dirty {
+            self.dirty = false;
+            self.rebuild(chat);
+        } else {
+            let messages = chat.channel_messages();
+            for change in changes {
+                let id = if let Change::Added(id) = change {
+                    id
+                } else {
+                    continue;
+                };
+                // Messages in other channels aren't shown
+                if let Some(idx) = messages.iter().rposition(|v| v.id == id) {
+                    let prev = if idx > 0 { Some(messages[idx - 1]) } else { None };
+                    self.add(chat, messages[idx], prev);
+                }
+            }
+            for id in mem::replace(&mut self.refresh, Vec::new()) {
+                self.refresh_message(chat, id);
+            }
+        }
+        self.try_jump();
+
+        if focus {
+            chat.editing()
+                .and_then(|id| self.nodes.get(&id))
+                .and_then(|v| query!(v, textbox).next())
+        } else {
+            None
+        }
+    }
+
+    /// Opens the reaction picker for the message or
+    /// closes it if its already open
+    pub fn toggle_picker(&mut self, id: MessageId) {
+        self.refresh.extend(self.picker);
+        self.picker = if self.picker == Some(id) {
+            None
+        } else {
+            self.refresh.push(id);
+            Some(id)
+        };
+    }
+
+    /// Closes the reaction picker if its open
+    pub fn close_picker(&mut self) {
+        self.refresh.extend(self.picker.take());
+    }
+
+    fn update_reply_bar(&self, chat: &Chat, id: Option<MessageId>) {
+        let msg = id.and_then(|id| chat.message(id));
+        self.reply_bar.set_property("active", msg.is_some());
+        if let Some(msg) = msg {
+            if let Some(name) = query!(self.reply_bar, label > @text).nth(1) {
+                name.set_text(msg.author.clone());
+                name.set_property("col", msg.colour.clone());
+            }
+        }
+    }
+
+    fn update_typing(&self, chat: &Chat) {
+        let users = chat.typing();
+        let text = match users.len() {
+            0 => String::new(),
+            1 => format!("{} is typing\u{2026}", users[0]),
+            2 => format!("{} and {} are typing\u{2026}", users[0], users[1]),
+            3 => format!("{}, {} and {} are typing\u{2026}", users[0], users[1], users[2]),
+            _ => "Several people are typing\u{2026}".to_owned(),
+        };
+        self.typing.set_property("active", !users.is_empty());
+        if let Some(txt) = query!(self.typing, @text).next() {
+            txt.set_text(text);
+        }
+    }
+
+    /// Updates the selected and unread states of the channels
+    /// and the server they are in. Muted channels never show
+    /// as unread.
+    pub fn update_unread(&self, chat: &Chat) {
+        let mut server_unread = false;
+        let mut server_mentions = 0;
+        for channel in query!(self.channels, channel) {
+            let name = if let Some(name) = channel.get_property::<String>("name") {
+                name
+            } else {
+                continue;
+            };
+            let selected = name == chat.channel();
+            let muted = chat.is_muted(&name);
+            let unread = !selected && !muted && chat.is_unread(&name);
+            let mentions = if selected || muted { 0 } else { chat.mentions(&name) };
+            server_unread |= unread;
+            server_mentions += mentions;
+            channel.set_property("selected", selected);
+            channel.set_property("muted", muted);
+            channel.set_property("unread", unread);
+            set_badge(&channel, mentions);
+        }
+        self.server.set_property("unread", server_unread);
+        set_badge(&self.server, server_mentions);
+    }
+
+    /// Scrolls the chat history up by the amount of pixels,
+    /// negative values scroll down
+    pub fn scroll_by(&self, amount: i32) {
+        use std::cmp;
+        let scroll = self.area.get_property::<i32>("scroll").unwrap_or(0) + amount;
+        let max = match (self.area.render_position(), self.content.render_position()) {
+            (Some(area), Some(content)) => cmp::max(0, content.height - area.height),
+            _ => 0,
+        };
+        self.area.set_property("scroll", cmp::max(0, cmp::min(max, scroll)));
+    }
+
+    /// Scrolls the message into view and highlights it.
+    ///
+    /// Happens during the next update as the message may be
+    /// in a channel that is about to be switched to.
+    pub fn jump_to(&mut self, id: MessageId) {
+        self.jump = Some(id);
+    }
+
+    fn try_jump(&mut self) {
+        let node = self.jump.and_then(|id| self.nodes.get(&id)).cloned();
+        let node = if let Some(node) = node {
+            node
+        } else {
+            // Not in the channel being viewed
+            self.jump = None;
+            return;
+        };
+        let target = if let Some(target) = node.render_position() {
+            target
+        } else {
+            // Recreated nodes need laying out first
+            return;
+        };
+        if let Some(area) = self.area.render_position() {
+            let area_center = area.y + area.height / 2;
+            let target_center = target.y + target.height / 2;
+            self.scroll_by(area_center - target_center);
+        }
+        node.set_property("highlight", true);
+        node.set_property("highlight_timer", HIGHLIGHT_TIME);
+        self.jump = None;
+    }
+
+    /// Recreates every message node from the model
+    pub fn rebuild(&mut self, chat: &Chat) {
+        for child in self.children.drain(..) {
+            self.content.remove_child(child);
+        }
+        self.nodes.clear();
+        self.refresh.clear();
+        self.editing = chat.editing();
+        let mut prev = None;
+        for msg in chat.channel_messages() {
+            self.add(chat, msg, prev);
+            prev = Some(msg);
+        }
+    }
+
+    /// Adds the node for the message to the end of the
+    /// history, grouping it with `prev` if it can be
+    fn add(&mut self, chat: &Chat, msg: &Message, prev: Option<&Message>) {
+        if let Some(prev) = prev {
+            if prev.local_date() != msg.local_date() {
+                let divider = node!{
+                    divider {
+                        @text(self.format.day(msg.local_date(), self.today))
+                    }
+                };
+                self.content.add_child(divider.clone());
+                self.children.push(divider);
+            }
+        }
+
+        let new = chat.new_since() == Some(msg.id);
+        if new {
+            let divider = node!{
+                divider {
+                    @text("NEW MESSAGES")
+                }
+            };
+            divider.set_property("new", true);
+            self.content.add_child(divider.clone());
+            self.children.push(divider);
+        }
+
+        // The divider breaks up the group
+        let node = if !new && prev.map_or(false, |v| msg.follows(v)) {
+            self.follow_node(msg)
+        } else {
+            self.message_node(msg)
+        };
+        node.set_property("msg_id", msg.id as i32);
+        self.fill(chat, &node, msg);
+        self.content.add_child(node.clone());
+        self.children.push(node.clone());
+        self.nodes.insert(msg.id, node);
+    }
+
+    /// Recreates the content of the message's node in place,
+    /// leaving the rest of the history alone
+    fn refresh_message(&mut self, chat: &Chat, id: MessageId) {
+        let (node, msg) = match (self.nodes.get(&id), chat.message(id)) {
+            (Some(node), Some(msg)) => (node.clone(), msg),
+            _ => return,
+        };
+        // Recreating the edit box would lose the text typed
+        // into it so far
+        if chat.editing() == Some(id) && query!(node, textbox).next().is_some() {
+            return;
+        }
+        let old = query!(node, reply_preview)
+            .chain(query!(node, content))
+            .chain(query!(node, textbox))
+            .chain(query!(node, edit_hint))
+            .chain(query!(node, actions))
+            .collect::<Vec<_>>();
+        for child in old {
+            node.remove_child(child);
+        }
+        self.fill(chat, &node, msg);
+    }
+
+    /// Adds everything below the author's name to the node
+    fn fill(&self, chat: &Chat, node: &ui::Node, msg: &Message) {
+        node.set_property("state", match msg.state {
+            SendState::Sent => "sent",
+            SendState::Sending => "sending",
+            SendState::Failed => "failed",
+        }.to_owned());
+        if let Some(reply_to) = msg.reply_to {
+            node.set_property("reply", true);
+            node.add_child(reply_preview(chat, reply_to));
+        }
+        self.add_body(chat, node, msg);
+    }
+
+    /// Creates the node for a message that starts a group,
+    /// with the author's icon and name
+    fn message_node(&self, msg: &Message) -> ui::Node {
+        let node = node!{
+            message {
+                icon
+                author
+            }
+        };
+        let author = query!(node, author).next().unwrap();
+        author.add_child({
+            let t = ui::Node::new_text(msg.author.clone());
+            t.set_property("col", msg.colour.clone());
+            t
+        });
+        author.add_child(ui::Node::new_text(
+            format!(" {}", self.format.timestamp(msg.time, self.today))
+        ));
+        node
+    }
+
+    /// Creates the node for a message following on from another
+    /// message by the same author. Only the content is shown
+    /// with the time appearing on hover.
+    fn follow_node(&self, msg: &Message) -> ui::Node {
+        let node = node!{
+            message {
+                time {
+                    @text(self.format.clock(msg.time))
+                }
+            }
+        };
+        node.set_property("follow", true);
+        node
+    }
+
+    /// Adds the message's content and hover actions, or
+    /// the inline edit box if its being edited
+    fn add_body(&self, chat: &Chat, node: &ui::Node, msg: &Message) {
+        if chat.editing() == Some(msg.id) {
+            let edit = node!{
+                textbox {
+                    content {
+                        @text(msg.content.clone())
+                    }
+                }
+            };
+            edit.set_property("edit_id", msg.id as i32);
+            node.add_child(edit);
+            node.add_child(node!{
+                edit_hint {
+                    @text("escape to cancel \u{2022} enter to save")
+                }
+            });
+            return;
+        }
+
+        let content = node!(content);
+        markdown::build_content(&content, &msg.content, &self.names);
+        if msg.edited.is_some() {
+            let t = ui::Node::new_text(" (edited)");
+            t.set_property("edited", true);
+            content.add_child(t);
+        }
+        if !msg.reactions.is_empty() {
+            let row = node!(reactions);
+            for reaction in &msg.reactions {
+                row.add_child(reaction_pill(chat, msg.id, reaction));
+            }
+            let add = reaction_node(msg.id, "+");
+            add.set_property("add", true);
+            row.add_child(add);
+            content.add_child(row);
+        }
+        if self.picker == Some(msg.id) {
+            let picker = node!(react_picker);
+            let mut seen = Vec::new();
+            for emoji in emoji::EMOJI {
+                // Some emoji have multiple shortcodes
+                if !seen.contains(&emoji.emoji) {
+                    seen.push(emoji.emoji);
+                    let pick = reaction_node(msg.id, emoji.emoji);
+                    pick.set_property("emoji", emoji.emoji.to_owned());
+                    picker.add_child(pick);
+                }
+            }
+            content.add_child(picker);
+        }
+        if msg.state == SendState::Failed {
+            let error = node!{
+                send_error {
+                    @text("Message failed to send. ")
+                }
+            };
+            error.add_child(action_button(msg.id, "retry", "Retry"));
+            error.add_child(ui::Node::new_text(" \u{2022} "));
+            error.add_child(action_button(msg.id, "delete", "Delete"));
+            content.add_child(error);
+        }
+        node.add_child(content);
+
+        // The server doesn't know about the message yet so
+        // there is nothing to act on
+        if msg.state != SendState::Sent {
+            return;
+        }
+        let actions = node!(actions);
+        actions.add_child(action_button(msg.id, "reply", "Reply"));
+        actions.add_child(action_button(msg.id, "react", "React"));
+        if chat.is_own(msg) {
+            actions.add_child(action_button(msg.id, "edit", "Edit"));
+            actions.add_child(action_button(msg.id, "delete", "Delete"));
+        }
+        node.add_child(actions);
+    }
+}
+
+/// Creates a button shown when hovering over a message
+fn action_button(id: MessageId, action: &str, label: &str) -> ui::Node {
+    let button = node!{
+        button {
+            @text(label)
+        }
+    };
+    button.set_property("action", action.to_owned());
+    button.set_property("msg_id", id as i32);
+    button
+}
+
+/// Creates the small quote of the replied to message shown
+/// above a reply
+fn reply_preview(chat: &Chat, id: MessageId) -> ui::Node {
+    let preview = node!(reply_preview);
+    preview.set_property("msg_id", id as i32);
+    if let Some(msg) = chat.message(id) {
+        preview.add_child({
+            let t = ui::Node::new_text(format!("@{} ", msg.author));
+            t.set_property("col", msg.colour.clone());
+            t
+        });
+        let line = msg.content.lines().next().unwrap_or("");
+        let mut snippet = line.chars().take(80).collect::<String>();
+        if snippet.len() < msg.content.len() {
+            snippet.push('\u{2026}');
+        }
+        preview.add_child(ui::Node::new_text(snippet));
+    } else {
+        preview.set_property("deleted", true);
+        preview.add_child(ui::Node::new_text("Original message was deleted"));
+    }
+    preview
+}
+
+fn reaction_node(id: MessageId, label: &str) -> ui::Node {
+    let node = node!{
+        reaction {
+            @text(label)
+        }
+    };
+    node.set_property("msg_id", id as i32);
+    node
+}
+
+/// Creates a pill showing the emoji and how many people
+/// reacted with it
+fn reaction_pill(chat: &Chat, id: MessageId, reaction: &Reaction) -> ui::Node {
+    let pill = reaction_node(id, &format!("{} {}", reaction.emoji, reaction.users.len()));
+    pill.set_property("emoji", reaction.emoji.clone());
+    pill.set_property("own", reaction.users.contains(&chat.user));
+    pill
+}
+
+/// Shows the number of mentions in a red badge on the node,
+/// removing the badge when there are none
+fn set_badge(node: &ui::Node, mentions: usize) {
+    let badge = query!(node, badge).next();
+    match (badge, mentions) {
+        (Some(badge), 0) => node.remove_child(badge),
+        (Some(badge), _) => if let Some(txt) = query!(badge, @text).next() {
+            txt.set_text(mentions.to_string());
+        },
+        (None, 0) => {},
+        (None, _) => node.add_child(node!{
+            badge {
+                @text(mentions.to_string())
+            }
+        }),
+    }
+}
+
+/// Returns the id of the message the node was created for
+pub fn message_id(node: &ui::Node) -> Option<MessageId> {
+    node.get_property::<i32>("msg_id").map(|v| v as MessageId)
+}
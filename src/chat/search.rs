@@ -0,0 +1,351 @@
+//! Full-text search over the chat history.
+//!
+//! Messages are added to an inverted index as they are sent
+//! or edited so searching only looks at the messages that
+//! contain the words searched for.
+
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use chrono::prelude::*;
+
+use markdown;
+use super::{Message, MessageId};
+
+/// A parsed search, e.g.
+/// `from:Thinkofname in:general has:link after:2017-08-01 "test message"`
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Query {
+    /// Words that must all appear
+    pub words: Vec<String>,
+    /// Runs of words that must appear next to each other
+    pub phrases: Vec<Vec<String>>,
+    /// The author, lowercased
+    pub from: Option<String>,
+    /// The channel, lowercased
+    pub channel: Option<String>,
+    pub has_link: bool,
+    /// Only messages sent before this day
+    pub before: Option<NaiveDate>,
+    /// Only messages sent after this day
+    pub after: Option<NaiveDate>,
+}
+
+impl Query {
+    pub fn parse(src: &str) -> Query {
+        let mut query = Query::default();
+        for (term, quoted) in terms(src) {
+            if quoted {
+                let mut words = tokenize(&term);
+                if words.len() > 1 {
+                    query.phrases.push(words);
+                } else {
+                    query.words.append(&mut words);
+                }
+                continue;
+            }
+            let (key, value) = match term.find(':') {
+                Some(pos) => (&term[..pos], &term[pos + 1..]),
+                None => ("", &term[..]),
+            };
+            let date = || NaiveDate::parse_from_str(value, "%Y-%m-%d").ok();
+            match (key, value) {
+                ("from", _) if !value.is_empty() => {
+                    query.from = Some(value.trim_left_matches('@').to_lowercase());
+                },
+                ("in", _) if !value.is_empty() => {
+                    query.channel = Some(value.trim_left_matches('#').to_lowercase());
+                },
+                ("has", "link") => query.has_link = true,
+                ("before", _) if date().is_some() => query.before = date(),
+                ("after", _) if date().is_some() => query.after = date(),
+                // Anything else is searched for as text
+                _ => query.words.extend(tokenize(&term)),
+            }
+        }
+        query
+    }
+
+    /// Returns whether the query would match every message
+    pub fn is_empty(&self) -> bool {
+        *self == Query::default()
+    }
+}
+
+/// Splits the search into terms, keeping text in quotes
+/// together. Quotes after a `key:` apply to the value so
+/// `from:"Rust User"` works.
+///
+/// Returns each term with whether it was a quoted phrase.
+fn terms(src: &str) -> Vec<(String, bool)> {
+    let mut terms = Vec::new();
+    let mut current = String::new();
+    let mut in_quotes = false;
+    let mut phrase = false;
+    for c in src.chars() {
+        match c {
+            '"' => {
+                if !in_quotes && current.is_empty() {
+                    phrase = true;
+                }
+                in_quotes = !in_quotes;
+            },
+            c if c.is_whitespace() && !in_quotes => {
+                if !current.is_empty() {
+                    terms.push((current.clone(), phrase));
+                    current.clear();
+                }
+                phrase = false;
+            },
+            c => current.push(c),
+        }
+    }
+    if !current.is_empty() {
+        terms.push((current, phrase));
+    }
+    terms
+}
+
+/// Splits the text into lowercase words
+fn tokenize(text: &str) -> Vec<String> {
+    text.to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|v| !v.is_empty())
+        .map(|v| v.to_owned())
+        .collect()
+}
+
+/// What the filters need to know about an indexed message
+struct Entry {
+    author: String,
+    channel: String,
+    time: DateTime<Utc>,
+    date: NaiveDate,
+    has_link: bool,
+    /// The distinct words in the message, used to remove it
+    /// from the index again
+    words: BTreeSet<String>,
+}
+
+/// An inverted index of the messages' words
+pub struct Index {
+    /// The messages each word appears in with the positions
+    /// it appears at
+    postings: HashMap<String, BTreeMap<MessageId, Vec<usize>>>,
+    entries: BTreeMap<MessageId, Entry>,
+}
+
+impl Index {
+    pub fn new() -> Index {
+        Index {
+            postings: HashMap::new(),
+            entries: BTreeMap::new(),
+        }
+    }
+
+    /// Adds the message to the index, replacing the old
+    /// version if it was edited
+    pub fn insert(&mut self, msg: &Message) {
+        self.remove(msg.id);
+        let words = tokenize(&msg.content);
+        for (pos, word) in words.iter().enumerate() {
+            self.postings.entry(word.clone())
+                .or_insert_with(BTreeMap::new)
+                .entry(msg.id)
+                .or_insert_with(Vec::new)
+                .push(pos);
+        }
+        // Only urls matter so mentions don't need linking
+        let has_link = markdown::parse(&msg.content, &[]).iter()
+            .any(|block| match *block {
+                markdown::Block::Text(ref spans) | markdown::Block::Quote(ref spans) => spans.iter()
+                    .any(|v| match v.link {
+                        Some(markdown::Link::Url(_)) => true,
+                        _ => false,
+                    }),
+                markdown::Block::Code{..} => false,
+            });
+        self.entries.insert(msg.id, Entry {
+            author: msg.author.to_lowercase(),
+            channel: msg.channel.to_lowercase(),
+            time: msg.time,
+            date: msg.time.with_timezone(&Local).naive_local().date(),
+            has_link: has_link,
+            words: words.into_iter().collect(),
+        });
+    }
+
+    pub fn remove(&mut self, id: MessageId) {
+        let entry = if let Some(entry) = self.entries.remove(&id) {
+            entry
+        } else {
+            return;
+        };
+        for word in entry.words {
+            let empty = self.postings.get_mut(&word).map_or(false, |v| {
+                v.remove(&id);
+                v.is_empty()
+            });
+            if empty {
+                self.postings.remove(&word);
+            }
+        }
+    }
+
+    /// Returns the messages matching the query, newest first
+    pub fn search(&self, query: &Query) -> Vec<MessageId> {
+        let mut words = query.words.iter()
+            .chain(query.phrases.iter().flat_map(|v| v.iter()))
+            .collect::<Vec<_>>();
+        // Starting with the rarest word keeps the candidates
+        // small
+        words.sort_by_key(|v| self.postings.get(*v).map_or(0, |v| v.len()));
+
+        let candidates: Vec<MessageId> = if let Some(first) = words.first() {
+            let postings = if let Some(postings) = self.postings.get(*first) {
+                postings
+            } else {
+                return Vec::new();
+            };
+            postings.keys()
+                .cloned()
+                .filter(|id| words[1..].iter()
+                    .all(|w| self.postings.get(*w).map_or(false, |v| v.contains_key(id))))
+                .collect()
+        } else {
+            self.entries.keys().cloned().collect()
+        };
+
+        let mut results = candidates.into_iter()
+            .filter_map(|id| self.entries.get(&id)
+                .and_then(|v| if self.matches(query, id, v) { Some((v.time, id)) } else { None }))
+            .collect::<Vec<_>>();
+        // Ids don't follow the time messages were sent in when
+        // older messages arrive later
+        results.sort_by_key(|&v| Reverse(v));
+        results.into_iter().map(|v| v.1).collect()
+    }
+
+    /// Checks the filters and phrases against the message
+    fn matches(&self, query: &Query, id: MessageId, entry: &Entry) -> bool {
+        query.from.as_ref().map_or(true, |v| *v == entry.author)
+            && query.channel.as_ref().map_or(true, |v| *v == entry.channel)
+            && (!query.has_link || entry.has_link)
+            && query.before.map_or(true, |v| entry.date < v)
+            && query.after.map_or(true, |v| entry.date > v)
+            && query.phrases.iter().all(|v| self.has_phrase(id, v))
+    }
+
+    fn has_phrase(&self, id: MessageId, phrase: &[String]) -> bool {
+        let positions = phrase.iter()
+            .map(|w| self.postings.get(w).and_then(|v| v.get(&id)))
+            .collect::<Option<Vec<_>>>();
+        let positions = if let Some(positions) = positions {
+            positions
+        } else {
+            return false;
+        };
+        positions[0].iter()
+            .any(|&start| positions[1..].iter()
+                .enumerate()
+                .all(|(i, v)| v.contains(&(start + i + 1))))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::prelude::*;
+    use chat::{Message, SendState};
+    use super::*;
+
+    fn message(id: u64, author: &str, channel: &str, day: u32, content: &str) -> Message {
+        Message {
+            id: id,
+            channel: channel.to_owned(),
+            author: author.to_owned(),
+            colour: String::new(),
+            time: Utc.ymd(2017, 8, day).and_hms(12, 0, 0),
+            content: content.to_owned(),
+            edited: None,
+            reply_to: None,
+            reactions: Vec::new(),
+            pinned: false,
+            state: SendState::Sent,
+        }
+    }
+
+    fn index() -> Index {
+        let mut index = Index::new();
+        index.insert(&message(1, "Thinkofname", "general", 1, "a test message"));
+        index.insert(&message(2, "Rust User", "general", 10, "message test, see https://example.com"));
+        // Older than the others but arrived last
+        index.insert(&message(3, "Thinkofname", "random", 5, "Another TEST message"));
+        index
+    }
+
+    #[test]
+    fn terms_keep_quotes_together() {
+        assert_eq!(terms(r#"hello "test message" from:"Rust User""#), vec![
+            ("hello".to_owned(), false),
+            ("test message".to_owned(), true),
+            ("from:Rust User".to_owned(), false),
+        ]);
+    }
+
+    #[test]
+    fn parse_filters() {
+        let query = Query::parse(r#"from:@Thinkofname in:#General has:link after:2017-08-01 before:2017-09-01 "test message" hello"#);
+        assert_eq!(query, Query {
+            words: vec!["hello".to_owned()],
+            phrases: vec![vec!["test".to_owned(), "message".to_owned()]],
+            from: Some("thinkofname".to_owned()),
+            channel: Some("general".to_owned()),
+            has_link: true,
+            before: Some(NaiveDate::from_ymd(2017, 9, 1)),
+            after: Some(NaiveDate::from_ymd(2017, 8, 1)),
+        });
+    }
+
+    #[test]
+    fn parse_unknown_filters_as_words() {
+        let query = Query::parse(r#"has:image before:never "single""#);
+        assert_eq!(query.words, vec!["has", "image", "before", "never", "single"]);
+        assert!(!query.has_link);
+        assert_eq!(query.before, None);
+        assert!(Query::parse("  ").is_empty());
+    }
+
+    #[test]
+    fn search_newest_first() {
+        let index = index();
+        assert_eq!(index.search(&Query::parse("test")), vec![2, 3, 1]);
+        assert_eq!(index.search(&Query::parse("")), vec![2, 3, 1]);
+        assert_eq!(index.search(&Query::parse("missing")), Vec::<u64>::new());
+    }
+
+    #[test]
+    fn search_phrases() {
+        let index = index();
+        assert_eq!(index.search(&Query::parse(r#""test message""#)), vec![3, 1]);
+        assert_eq!(index.search(&Query::parse(r#""message test""#)), vec![2]);
+    }
+
+    #[test]
+    fn search_filters() {
+        let index = index();
+        assert_eq!(index.search(&Query::parse("test from:thinkofname")), vec![3, 1]);
+        assert_eq!(index.search(&Query::parse("test in:random")), vec![3]);
+        assert_eq!(index.search(&Query::parse("has:link")), vec![2]);
+        assert_eq!(index.search(&Query::parse("after:2017-08-03 before:2017-08-07")), vec![3]);
+    }
+
+    #[test]
+    fn edits_and_removals_update_the_index() {
+        let mut index = index();
+        index.insert(&message(1, "Thinkofname", "general", 1, "edited"));
+        assert_eq!(index.search(&Query::parse("test")), vec![2, 3]);
+        assert_eq!(index.search(&Query::parse("edited")), vec![1]);
+        index.remove(1);
+        assert_eq!(index.search(&Query::parse("edited")), Vec::<u64>::new());
+        assert!(index.postings.get("edited").is_none());
+    }
+}
@@ -0,0 +1,120 @@
+//! Formatting of message timestamps.
+//!
+//! Times are stored in UTC and shown in local time relative
+//! to the current day.
+
+use chrono::prelude::*;
+use chrono::Duration;
+
+/// Whether times are shown with a 12 or 24 hour clock
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Clock {
+    /// `1:37 PM`
+    #[serde(rename = "12")]
+    Twelve,
+    /// `13:37`
+    #[serde(rename = "24")]
+    TwentyFour,
+}
+
+/// The order full dates are shown in
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DateOrder {
+    /// `15/10/2026`
+    #[serde(rename = "dmy")]
+    DayMonthYear,
+    /// `10/15/2026`
+    #[serde(rename = "mdy")]
+    MonthDayYear,
+    /// `2026-10-15`
+    #[serde(rename = "iso")]
+    Iso,
+}
+
+/// The user's timestamp preferences
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TimeFormat {
+    pub clock: Clock,
+    pub date: DateOrder,
+}
+
+impl Default for TimeFormat {
+    fn default() -> TimeFormat {
+        TimeFormat {
+            clock: Clock::TwentyFour,
+            date: DateOrder::DayMonthYear,
+        }
+    }
+}
+
+impl TimeFormat {
+    /// Picks the preferences based on the locale set by the
+    /// `LC_ALL`, `LC_TIME` or `LANG` environment variables
+    pub fn from_locale() -> TimeFormat {
+        use std::env;
+        let locale = ["LC_ALL", "LC_TIME", "LANG"].iter()
+            .filter_map(|v| env::var(v).ok())
+            .find(|v| !v.is_empty())
+            .unwrap_or_default();
+        let region = locale.split('.').next().unwrap_or("");
+        let lang = region.split('_').next().unwrap_or("");
+        match (lang, region) {
+            (_, "en_US") | (_, "en_PH") => TimeFormat {
+                clock: Clock::Twelve,
+                date: DateOrder::MonthDayYear,
+            },
+            (_, "en_AU") | (_, "en_NZ") | (_, "en_IN") | (_, "en_CA") => TimeFormat {
+                clock: Clock::Twelve,
+                date: DateOrder::DayMonthYear,
+            },
+            ("sv", _) | ("lt", _) | ("hu", _) | ("zh", _) | ("ja", _) | ("ko", _) => TimeFormat {
+                clock: Clock::TwentyFour,
+                date: DateOrder::Iso,
+            },
+            _ => TimeFormat::default(),
+        }
+    }
+
+    /// Formats just the time of day
+    pub fn clock(&self, time: DateTime<Utc>) -> String {
+        let time = time.with_timezone(&Local);
+        match self.clock {
+            Clock::Twelve => time.format("%-I:%M %p").to_string(),
+            Clock::TwentyFour => time.format("%H:%M").to_string(),
+        }
+    }
+
+    /// Formats just the date
+    pub fn date(&self, date: Date<Local>) -> String {
+        match self.date {
+            DateOrder::DayMonthYear => date.format("%d/%m/%Y").to_string(),
+            DateOrder::MonthDayYear => date.format("%m/%d/%Y").to_string(),
+            DateOrder::Iso => date.format("%Y-%m-%d").to_string(),
+        }
+    }
+
+    /// Formats the time relative to `today`: "Today at",
+    /// "Yesterday at" or the full date for anything older
+    pub fn timestamp(&self, time: DateTime<Utc>, today: Date<Local>) -> String {
+        let date = time.with_timezone(&Local).date();
+        if date == today {
+            format!("Today at {}", self.clock(time))
+        } else if date == today - Duration::days(1) {
+            format!("Yesterday at {}", self.clock(time))
+        } else {
+            self.date(date)
+        }
+    }
+
+    /// Formats the day relative to `today` for use
+    /// in dividers
+    pub fn day(&self, date: Date<Local>, today: Date<Local>) -> String {
+        if date == today {
+            "Today".to_owned()
+        } else if date == today - Duration::days(1) {
+            "Yesterday".to_owned()
+        } else {
+            date.format("%B %-d, %Y").to_string()
+        }
+    }
+}
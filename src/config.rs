@@ -0,0 +1,378 @@
+//! The user's settings.
+//!
+//! Settings are stored as TOML in
+//! `$XDG_CONFIG_HOME/stylish_discord/config.toml`. Anything
+//! missing from the file uses its default so the file only
+//! needs the settings the user changed. The settings screen
+//! writes the whole file back whenever something changes.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io::{Read, Write};
+use std::path::PathBuf;
+
+use sdl2::keyboard::{self, Keycode, Mod};
+use toml;
+
+use paths;
+use chat::time::{Clock, DateOrder, TimeFormat};
+
+const FILE: &str = "config.toml";
+
+/// The actions that can be bound to keys with their labels
+/// and default bindings
+pub const ACTIONS: &[(&str, &str, &str)] = &[
+    ("settings", "Open Settings", "Ctrl+,"),
+    ("search", "Search", "Ctrl+F"),
+    ("toggle_members", "Toggle Member List", "Ctrl+U"),
+    ("mark_read", "Mark Channel As Read", "Shift+Escape"),
+    ("reload_styles", "Reload Styles", "F5"),
+];
+
+/// The frame caps offered by the settings screen, 0 is
+/// unlimited
+pub const FRAME_CAPS: &[u32] = &[30, 60, 120, 144, 0];
+pub const MIN_FONT_SCALE: f64 = 0.75;
+pub const MAX_FONT_SCALE: f64 = 1.5;
+/// How much the settings screen changes the font scale by
+pub const FONT_SCALE_STEP: f64 = 0.05;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Theme {
+    #[serde(rename = "dark")]
+    Dark,
+    #[serde(rename = "light")]
+    Light,
+}
+
+/// Every theme in the order the settings screen lists them
+pub const THEMES: &[Theme] = &[Theme::Dark, Theme::Light];
+
+impl Theme {
+    /// The style group loaded on top of `base` for the theme
+    pub fn styles(self) -> Option<&'static str> {
+        match self {
+            Theme::Dark => None,
+            Theme::Light => Some("light"),
+        }
+    }
+}
+
+/// How message times are shown. Settings that aren't set
+/// are picked from the locale.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Timestamps {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub clock: Option<Clock>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub date: Option<DateOrder>,
+}
+
+impl Timestamps {
+    pub fn format(&self) -> TimeFormat {
+        let locale = TimeFormat::from_locale();
+        TimeFormat {
+            clock: self.clock.unwrap_or(locale.clock),
+            date: self.date.unwrap_or(locale.date),
+        }
+    }
+}
+
+/// The size and position of the window when it was
+/// last closed
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WindowGeometry {
+    pub width: u32,
+    pub height: u32,
+    /// The window is centered when the position isn't set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub x: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub y: Option<i32>,
+    pub maximized: bool,
+}
+
+impl Default for WindowGeometry {
+    fn default() -> WindowGeometry {
+        WindowGeometry {
+            width: 1024,
+            height: 640,
+            x: None,
+            y: None,
+            maximized: true,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub theme: Theme,
+    /// Multiplies the size of all text
+    pub font_scale: f64,
+    /// The most frames drawn a second, 0 for no limit
+    pub frame_cap: u32,
+    pub timestamps: Timestamps,
+    /// The keys bound to each action in `ACTIONS`, e.g.
+    /// `search = "Ctrl+F"`
+    pub keybindings: BTreeMap<String, String>,
+    pub window: WindowGeometry,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            theme: Theme::Dark,
+            font_scale: 1.0,
+            frame_cap: 60,
+            timestamps: Timestamps::default(),
+            keybindings: ACTIONS.iter()
+                .map(|&(action, _, key)| (action.to_owned(), key.to_owned()))
+                .collect(),
+            window: WindowGeometry::default(),
+        }
+    }
+}
+
+/// Returns the path of the settings file
+pub fn path() -> PathBuf {
+    paths::config_dir().join(FILE)
+}
+
+impl Config {
+    /// Loads the settings, using the defaults if the file
+    /// doesn't exist or can't be read
+    pub fn load() -> Config {
+        let path = path();
+        let mut data = String::new();
+        let mut config = match fs::File::open(&path) {
+            Ok(mut f) => {
+                let res = match f.read_to_string(&mut data) {
+                    Ok(_) => toml::from_str::<Config>(&data).map_err(|v| v.to_string()),
+                    Err(err) => Err(err.to_string()),
+                };
+                match res {
+                    Ok(config) => config,
+                    Err(err) => {
+                        println!("Failed to load {:?}, using the defaults: {}", path, err);
+                        Config::default()
+                    },
+                }
+            },
+            Err(_) => Config::default(),
+        };
+        // Actions added since the file was written or
+        // missing from it keep their default keys, as do ones
+        // bound to keys that type text which older versions
+        // allowed
+        for &(action, _, key) in ACTIONS {
+            let binding = config.keybindings.entry(action.to_owned())
+                .or_insert_with(|| key.to_owned());
+            if Binding::parse(binding).map_or(false, |v| v.types_text()) {
+                *binding = key.to_owned();
+            }
+        }
+        let scale = config.font_scale;
+        config.set_font_scale(scale);
+        config
+    }
+
+    /// Writes the settings back to the file
+    pub fn save(&self) {
+        let dir = paths::config_dir();
+        let tmp = dir.join(format!("{}.tmp", FILE));
+        let res = toml::to_string(self)
+            .map_err(|v| v.to_string())
+            .and_then(|data| fs::File::create(&tmp)
+                .and_then(|mut f| f.write_all(data.as_bytes()))
+                .and_then(|_| fs::rename(&tmp, dir.join(FILE)))
+                .map_err(|v| v.to_string()));
+        if let Err(err) = res {
+            println!("Failed to save the settings: {}", err);
+        }
+    }
+
+    /// Sets the font scale, keeping it within the range
+    /// the layout copes with
+    pub fn set_font_scale(&mut self, scale: f64) {
+        self.font_scale = scale.max(MIN_FONT_SCALE).min(MAX_FONT_SCALE);
+    }
+
+    /// Returns the key bound to the action if it is valid
+    pub fn binding(&self, action: &str) -> Option<Binding> {
+        self.keybindings.get(action).and_then(|v| Binding::parse(v))
+    }
+
+    /// Returns the action bound to the key if any
+    pub fn action(&self, key: Keycode, keymod: Mod) -> Option<&'static str> {
+        let pressed = Binding::from_event(key, keymod);
+        ACTIONS.iter()
+            .map(|v| v.0)
+            .find(|v| self.binding(v) == Some(pressed))
+    }
+}
+
+/// A key with the modifiers that must be held with it
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Binding {
+    pub key: Keycode,
+    pub ctrl: bool,
+    pub shift: bool,
+    pub alt: bool,
+}
+
+impl Binding {
+    /// Parses bindings like `Ctrl+Shift+F`
+    pub fn parse(src: &str) -> Option<Binding> {
+        // The key itself may be `+`, either on its own or
+        // after the `+` ending the modifiers
+        let (mods, key) = match src.rfind('+') {
+            Some(pos) if pos + 1 < src.len() => (&src[..pos], &src[pos + 1..]),
+            Some(pos) if pos == 0 || src[..pos].ends_with('+') => (&src[..pos], "+"),
+            Some(_) => return None,
+            None => ("", src),
+        };
+        let key = if let Some(key) = Keycode::from_name(key) {
+            key
+        } else {
+            return None;
+        };
+        let mut binding = Binding {
+            key: key,
+            ctrl: false,
+            shift: false,
+            alt: false,
+        };
+        for name in mods.split('+').filter(|v| !v.is_empty()) {
+            match name.to_lowercase().as_str() {
+                "ctrl" => binding.ctrl = true,
+                "shift" => binding.shift = true,
+                "alt" => binding.alt = true,
+                _ => return None,
+            }
+        }
+        Some(binding)
+    }
+
+    pub fn from_event(key: Keycode, keymod: Mod) -> Binding {
+        Binding {
+            key: key,
+            ctrl: keymod.intersects(keyboard::LCTRLMOD | keyboard::RCTRLMOD),
+            shift: keymod.intersects(keyboard::LSHIFTMOD | keyboard::RSHIFTMOD),
+            alt: keymod.intersects(keyboard::LALTMOD | keyboard::RALTMOD),
+        }
+    }
+
+    /// Returns whether pressing the binding would type or
+    /// edit text, so binding it would break textboxes
+    pub fn types_text(&self) -> bool {
+        if self.ctrl || self.alt {
+            return false;
+        }
+        match self.key {
+            Keycode::Space | Keycode::Backspace | Keycode::Delete
+            | Keycode::Return | Keycode::KpEnter | Keycode::Tab
+            | Keycode::Left | Keycode::Right | Keycode::Up | Keycode::Down
+            | Keycode::Home | Keycode::End | Keycode::PageUp | Keycode::PageDown => true,
+            key => key.name().chars().count() == 1,
+        }
+    }
+
+    /// Returns whether the key is a modifier which can't be
+    /// bound on its own
+    pub fn is_modifier(key: Keycode) -> bool {
+        match key {
+            Keycode::LCtrl | Keycode::RCtrl
+            | Keycode::LShift | Keycode::RShift
+            | Keycode::LAlt | Keycode::RAlt
+            | Keycode::LGui | Keycode::RGui => true,
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for Binding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.ctrl {
+            write!(f, "Ctrl+")?;
+        }
+        if self.shift {
+            write!(f, "Shift+")?;
+        }
+        if self.alt {
+            write!(f, "Alt+")?;
+        }
+        write!(f, "{}", self.key.name())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn binding(key: Keycode, ctrl: bool, shift: bool, alt: bool) -> Binding {
+        Binding {
+            key: key,
+            ctrl: ctrl,
+            shift: shift,
+            alt: alt,
+        }
+    }
+
+    #[test]
+    fn parse_modifiers() {
+        assert_eq!(Binding::parse("F"), Some(binding(Keycode::F, false, false, false)));
+        assert_eq!(Binding::parse("Ctrl+Shift+F"), Some(binding(Keycode::F, true, true, false)));
+        assert_eq!(Binding::parse("alt+CTRL+F"), Some(binding(Keycode::F, true, false, true)));
+        assert_eq!(Binding::parse("Shift+Escape"), Some(binding(Keycode::Escape, false, true, false)));
+    }
+
+    #[test]
+    fn parse_plus_key() {
+        assert_eq!(Binding::parse("+"), Some(binding(Keycode::Plus, false, false, false)));
+        assert_eq!(Binding::parse("Ctrl++"), Some(binding(Keycode::Plus, true, false, false)));
+        assert_eq!(Binding::parse("Ctrl+Shift++"), Some(binding(Keycode::Plus, true, true, false)));
+    }
+
+    #[test]
+    fn parse_invalid() {
+        assert_eq!(Binding::parse(""), None);
+        assert_eq!(Binding::parse("Super+F"), None);
+        assert_eq!(Binding::parse("Ctrl+"), None);
+        assert_eq!(Binding::parse("F+"), None);
+        assert_eq!(Binding::parse("Ctrl+NotAKey"), None);
+    }
+
+    #[test]
+    fn display_round_trip() {
+        for &(_, _, default) in ACTIONS {
+            let parsed = Binding::parse(default).unwrap();
+            assert_eq!(parsed.to_string(), default);
+        }
+        for src in &["Ctrl+Shift+Alt+F", "Ctrl++", "+", "Alt+Space"] {
+            assert_eq!(Binding::parse(src).unwrap().to_string(), *src);
+        }
+    }
+
+    #[test]
+    fn types_text() {
+        assert!(binding(Keycode::F, false, false, false).types_text());
+        assert!(binding(Keycode::F, false, true, false).types_text());
+        assert!(binding(Keycode::Space, false, false, false).types_text());
+        assert!(binding(Keycode::Backspace, false, false, false).types_text());
+        assert!(binding(Keycode::Delete, false, true, false).types_text());
+        assert!(binding(Keycode::Return, false, false, false).types_text());
+        assert!(binding(Keycode::Tab, false, false, false).types_text());
+        assert!(binding(Keycode::Up, false, false, false).types_text());
+        assert!(binding(Keycode::Left, false, false, false).types_text());
+        assert!(!binding(Keycode::Backspace, true, false, false).types_text());
+        assert!(!binding(Keycode::Up, false, false, true).types_text());
+        assert!(!binding(Keycode::F, true, false, false).types_text());
+        assert!(!binding(Keycode::F, false, false, true).types_text());
+        assert!(!binding(Keycode::Escape, false, true, false).types_text());
+        assert!(!binding(Keycode::F5, false, false, false).types_text());
+    }
+}
@@ -0,0 +1,90 @@
+//! Emoji shortcodes.
+
+/// The group an emoji is shown under in the picker
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Category {
+    People,
+    Nature,
+    Food,
+    Activities,
+    Objects,
+    Symbols,
+}
+
+impl Category {
+    /// All categories in the order they are displayed
+    pub const ALL: &'static [Category] = &[
+        Category::People,
+        Category::Nature,
+        Category::Food,
+        Category::Activities,
+        Category::Objects,
+        Category::Symbols,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Category::People => "PEOPLE",
+            Category::Nature => "NATURE",
+            Category::Food => "FOOD & DRINK",
+            Category::Activities => "ACTIVITIES",
+            Category::Objects => "OBJECTS",
+            Category::Symbols => "SYMBOLS",
+        }
+    }
+}
+
+/// A known emoji
+#[derive(Clone, Copy, Debug)]
+pub struct Emoji {
+    /// The shortcode without colons
+    pub name: &'static str,
+    pub emoji: &'static str,
+    pub category: Category,
+}
+
+macro_rules! emoji {
+    ($($cat:ident: $($name:expr => $emoji:expr),*;)*) => (
+        &[$($(
+            Emoji { name: $name, emoji: $emoji, category: Category::$cat },
+        )*)*]
+    )
+}
+
+/// Every known emoji. Emoji with multiple shortcodes
+/// appear once per shortcode.
+pub const EMOJI: &[Emoji] = emoji! {
+    People:
+        "smile" => "\u{1F604}", "grin" => "\u{1F601}", "joy" => "\u{1F602}",
+        "wink" => "\u{1F609}", "thinking" => "\u{1F914}", "cry" => "\u{1F622}",
+        "angry" => "\u{1F620}", "sunglasses" => "\u{1F60E}", "sweat_smile" => "\u{1F605}",
+        "thumbsup" => "\u{1F44D}", "+1" => "\u{1F44D}",
+        "thumbsdown" => "\u{1F44E}", "-1" => "\u{1F44E}",
+        "clap" => "\u{1F44F}", "wave" => "\u{1F44B}", "eyes" => "\u{1F440}",
+        "pray" => "\u{1F64F}", "muscle" => "\u{1F4AA}";
+    Nature:
+        "crab" => "\u{1F980}", "bug" => "\u{1F41B}", "cat" => "\u{1F431}",
+        "dog" => "\u{1F436}", "penguin" => "\u{1F427}", "snake" => "\u{1F40D}",
+        "sunflower" => "\u{1F33B}", "evergreen_tree" => "\u{1F332}", "sunny" => "\u{2600}";
+    Food:
+        "pizza" => "\u{1F355}", "coffee" => "\u{2615}", "cake" => "\u{1F370}",
+        "apple" => "\u{1F34E}", "taco" => "\u{1F32E}", "beer" => "\u{1F37A}";
+    Activities:
+        "tada" => "\u{1F389}", "video_game" => "\u{1F3AE}", "soccer" => "\u{26BD}",
+        "trophy" => "\u{1F3C6}", "guitar" => "\u{1F3B8}";
+    Objects:
+        "rocket" => "\u{1F680}", "computer" => "\u{1F4BB}", "bulb" => "\u{1F4A1}",
+        "wrench" => "\u{1F527}", "gear" => "\u{2699}", "lock" => "\u{1F512}",
+        "books" => "\u{1F4DA}";
+    Symbols:
+        "heart" => "\u{2764}", "fire" => "\u{1F525}", "sparkles" => "\u{2728}",
+        "100" => "\u{1F4AF}", "check" => "\u{2714}", "x" => "\u{274C}",
+        "warning" => "\u{26A0}", "question" => "\u{2753}";
+};
+
+/// Looks up the emoji for the shortcode (without colons)
+pub fn lookup(name: &str) -> Option<&'static str> {
+    EMOJI.iter()
+        .find(|v| v.name == name)
+        .map(|v| v.emoji)
+}
@@ -0,0 +1,254 @@
+//! Token level syntax highlighting for code blocks.
+//!
+//! This isn't a real parser for any of the languages, just enough
+//! of a scanner to colour keywords, strings, comments and numbers.
+
+/// The type of a highlighted token
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Kind {
+    Plain,
+    Keyword,
+    Type,
+    Literal,
+    String,
+    Number,
+    Comment,
+    Macro,
+    Key,
+    Variable,
+}
+
+impl Kind {
+    /// The colour to use for the token, `None` for the default
+    /// code colour
+    pub fn colour(self) -> Option<&'static str> {
+        match self {
+            Kind::Plain => None,
+            Kind::Keyword => Some("#c678dd"),
+            Kind::Type => Some("#e5c07b"),
+            Kind::Literal | Kind::Number => Some("#d19a66"),
+            Kind::String => Some("#98c379"),
+            Kind::Comment => Some("#5c6370"),
+            Kind::Macro => Some("#61afef"),
+            Kind::Key | Kind::Variable => Some("#e06c75"),
+        }
+    }
+}
+
+struct Language {
+    names: &'static [&'static str],
+    keywords: &'static [&'static str],
+    literals: &'static [&'static str],
+    line_comment: Option<&'static str>,
+    block_comment: Option<(&'static str, &'static str)>,
+    quotes: &'static [char],
+    /// Capitalized identifiers are types
+    capital_types: bool,
+    /// `ident!` is a macro call
+    macros: bool,
+    /// `$NAME` is a variable
+    variables: bool,
+    /// Identifiers/strings before a `=` or `:` are keys
+    keys: Option<char>,
+    /// `[name]` at the start of a line is a table header
+    tables: bool,
+}
+
+const LANGUAGES: &[Language] = &[
+    Language {
+        names: &["rust", "rs"],
+        keywords: &[
+            "as", "break", "const", "continue", "crate", "else", "enum", "extern",
+            "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move",
+            "mut", "pub", "ref", "return", "self", "Self", "static", "struct",
+            "super", "trait", "type", "unsafe", "use", "where", "while",
+        ],
+        literals: &["true", "false"],
+        line_comment: Some("//"),
+        block_comment: Some(("/*", "*/")),
+        quotes: &['"'],
+        capital_types: true,
+        macros: true,
+        variables: false,
+        keys: None,
+        tables: false,
+    },
+    Language {
+        names: &["toml"],
+        keywords: &[],
+        literals: &["true", "false"],
+        line_comment: Some("#"),
+        block_comment: None,
+        quotes: &['"', '\''],
+        capital_types: false,
+        macros: false,
+        variables: false,
+        keys: Some('='),
+        tables: true,
+    },
+    Language {
+        names: &["json"],
+        keywords: &[],
+        literals: &["true", "false", "null"],
+        line_comment: None,
+        block_comment: None,
+        quotes: &['"'],
+        capital_types: false,
+        macros: false,
+        variables: false,
+        keys: Some(':'),
+        tables: false,
+    },
+    Language {
+        names: &["sh", "bash", "shell", "zsh", "console"],
+        keywords: &[
+            "if", "then", "else", "elif", "fi", "for", "while", "until", "do",
+            "done", "case", "esac", "in", "function", "return", "export", "local",
+        ],
+        literals: &[],
+        line_comment: Some("#"),
+        block_comment: None,
+        quotes: &['"', '\''],
+        capital_types: false,
+        macros: false,
+        variables: true,
+        keys: None,
+        tables: false,
+    },
+];
+
+fn find_language(name: &str) -> Option<&'static Language> {
+    let name = name.to_lowercase();
+    LANGUAGES.iter()
+        .find(|l| l.names.contains(&name.as_str()))
+}
+
+/// Returns whether the named language can be highlighted
+pub fn is_supported(name: &str) -> bool {
+    find_language(name).is_some()
+}
+
+/// Highlights the code returning the tokens split into lines.
+///
+/// Returns `None` if the language isn't supported.
+pub fn highlight(lang: &str, code: &str) -> Option<Vec<Vec<(Kind, String)>>> {
+    let lang = if let Some(lang) = find_language(lang) {
+        lang
+    } else {
+        return None;
+    };
+
+    let mut lines = vec![Vec::new()];
+    let mut line_start = true;
+    let mut rest = code;
+    while !rest.is_empty() {
+        let (kind, len) = next_token(lang, rest, line_start);
+        let (token, next) = rest.split_at(len);
+        rest = next;
+        for (idx, part) in token.split('\n').enumerate() {
+            if idx != 0 {
+                lines.push(Vec::new());
+                line_start = true;
+            }
+            if part.is_empty() {
+                continue;
+            }
+            if !part.trim().is_empty() {
+                line_start = false;
+            }
+            let line = lines.last_mut().unwrap();
+            // Merge runs of the same kind to keep the node count down
+            if let Some(&mut (last_kind, ref mut text)) = line.last_mut() {
+                if last_kind == kind {
+                    text.push_str(part);
+                    continue;
+                }
+            }
+            line.push((kind, part.to_owned()));
+        }
+    }
+    Some(lines)
+}
+
+fn is_ident(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+/// Returns the kind and byte length of the token at the start
+/// of `src`
+fn next_token(lang: &Language, src: &str, line_start: bool) -> (Kind, usize) {
+    let c = src.chars().next().unwrap();
+
+    if let Some(comment) = lang.line_comment {
+        if src.starts_with(comment) {
+            return (Kind::Comment, src.find('\n').unwrap_or(src.len()));
+        }
+    }
+    if let Some((start, end)) = lang.block_comment {
+        if src.starts_with(start) {
+            let len = src[start.len()..].find(end)
+                .map_or(src.len(), |v| v + start.len() + end.len());
+            return (Kind::Comment, len);
+        }
+    }
+    if lang.tables && line_start && c == '[' {
+        return (Kind::Type, src.find(']').map_or(src.len(), |v| v + 1));
+    }
+    if lang.quotes.contains(&c) {
+        let mut len = src.len();
+        let mut escaped = false;
+        for (idx, n) in src.char_indices().skip(1) {
+            if escaped {
+                escaped = false;
+            } else if n == '\\' {
+                escaped = true;
+            } else if n == c {
+                len = idx + 1;
+                break;
+            }
+        }
+        if is_key(lang, &src[len..]) {
+            return (Kind::Key, len);
+        }
+        return (Kind::String, len);
+    }
+    if c.is_whitespace() {
+        return (Kind::Plain, src.find(|c: char| !c.is_whitespace()).unwrap_or(src.len()));
+    }
+    if c.is_digit(10) {
+        let len = src.find(|c: char| !(is_ident(c) || c == '.')).unwrap_or(src.len());
+        return (Kind::Number, len);
+    }
+    if lang.variables && c == '$' {
+        let len = if src[1..].starts_with('{') {
+            src.find('}').map_or(src.len(), |v| v + 1)
+        } else {
+            1 + src[1..].find(|c: char| !is_ident(c)).unwrap_or(src.len() - 1)
+        };
+        return (Kind::Variable, len);
+    }
+    if is_ident(c) {
+        let len = src.find(|c: char| !(is_ident(c) || (lang.keys.is_some() && c == '-')))
+            .unwrap_or(src.len());
+        let word = &src[..len];
+        let kind = if lang.keywords.contains(&word) {
+            Kind::Keyword
+        } else if lang.literals.contains(&word) {
+            Kind::Literal
+        } else if lang.macros && src[len..].starts_with('!') {
+            return (Kind::Macro, len + 1);
+        } else if lang.capital_types && c.is_uppercase() {
+            Kind::Type
+        } else if is_key(lang, &src[len..]) {
+            Kind::Key
+        } else {
+            Kind::Plain
+        };
+        return (kind, len);
+    }
+    (Kind::Plain, c.len_utf8())
+}
+
+fn is_key(lang: &Language, after: &str) -> bool {
+    lang.keys.map_or(false, |k| after.trim_left_matches(|c: char| c == ' ' || c == '\t').starts_with(k))
+}
@@ -1,18 +1,38 @@
-
 #[macro_use]
 extern crate stylish;
 extern crate stylish_webrender;
 extern crate sdl2;
 extern crate chrono;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate toml;
 
 pub mod ui;
 use ui::EventType;
 pub mod assets;
+pub mod markdown;
+pub mod highlight;
+pub mod emoji;
+pub mod chat;
+pub mod paths;
+pub mod picker;
+pub mod profile;
+pub mod members;
+pub mod presence;
+pub mod backend;
+pub mod categories;
+pub mod voice;
+pub mod channel_bar;
+pub mod store;
+pub mod outbox;
+pub mod config;
+pub mod settings;
 
 use std::time::{Duration, Instant};
 use std::thread;
 use sdl2::keyboard::Keycode;
-use sdl2::event::Event;
+use sdl2::event::{Event, WindowEvent};
 use chrono::prelude::*;
 
 fn main() {
@@ -27,25 +47,72 @@ fn main() {
     gl_attr.set_context_major_version(3);
     gl_attr.set_context_minor_version(2);
     gl_attr.set_context_profile(sdl2::video::GLProfile::Core);
-    let mut window = video.window("Discord-Rust", 1024, 640)
-        .position_centered()
-        .opengl()
-        .resizable()
-        .build()
-        .expect("Failed to open a window");
-    window.maximize();
+    let mut config = config::Config::load();
+    let mut window = {
+        let geometry = &config.window;
+        let mut builder = video.window("Discord-Rust", geometry.width, geometry.height);
+        if let (Some(x), Some(y)) = (geometry.x, geometry.y) {
+            builder.position(x, y);
+        } else {
+            builder.position_centered();
+        }
+        builder.opengl()
+            .resizable()
+            .build()
+            .expect("Failed to open a window")
+    };
+    if config.window.maximized {
+        window.maximize();
+    }
 
     let mut sdl_events = sdl.event_pump()
         .expect("Failed to get the event pump");
     let input = video.text_input();
+    let clipboard = video.clipboard();
+    let audio = match sdl.audio() {
+        Ok(audio) => Some(audio),
+        Err(err) => {
+            println!("Failed to initialize audio, voice will be silent: {}", err);
+            None
+        },
+    };
 
     let gl_context = window.gl_create_context().expect("Failed to create opengl context");
     window.gl_make_current(&gl_context).expect("Could not set current context.");
 
     let mut ui_manager = ui::Manager::new();
-    ui_manager.load_styles("base");
+    apply_styles(&mut ui_manager, &config);
     let root = ui_manager.create_node("main");
 
+    let mut chat = chat::Chat::new("Rust User", "#FFBF00", "general");
+    let mut members = members::Members::new();
+    if let Some(cache) = store::Cache::load() {
+        for user in cache.restore(&mut chat, &mut members) {
+            add_direct_message(&root, &user);
+        }
+    }
+    let mut outbox = outbox::Outbox::load(&mut chat);
+    let mut save_timer = store::SAVE_INTERVAL;
+    // Direct message channels only exist in the channel list
+    // so adding one is tracked here
+    let mut directs_changed = false;
+    let mut chat_view = chat::render::ChatView::new(&root);
+    let mut member_list = members::render::MemberList::new(&root);
+    let mut presence = presence::Presence::new(&root, &chat.user);
+    let mut voice = voice::Voice::new(&root, &chat.user, audio);
+    let mut backend = backend::Backend::new();
+    presence.refresh(&members);
+    let time_format = config.timestamps.format();
+    chat_view.set_format(time_format);
+    chat_view.set_names(mention_names(&chat, &members));
+    let mut emoji_picker = picker::EmojiPicker::new();
+    let mut context_menu = ui::menu::ContextMenu::new();
+    let mut profile_popout = profile::ProfilePopout::new();
+    let mut categories = categories::Categories::new(&root);
+    let mut channel_bar = channel_bar::ChannelBar::new(&root);
+    channel_bar.set_format(time_format);
+    let mut settings = settings::Settings::new();
+
     let mut ui_renderer = stylish_webrender::WebRenderer::new(
         |n| video.gl_get_proc_address(n),
         assets::AssetLoader,
@@ -67,8 +134,28 @@ fn main() {
             (diff.as_secs() * 1_000_000_000 + diff.subsec_nanos() as u64) as f64 / (1_000_000_000.0 / 60.0);
 
         let (width, height) = window.drawable_size();
+        let mut config_changed = false;
 
         for sdlevent in sdl_events.poll_iter() {
+            match sdlevent {
+                Event::TextInput{..} | Event::KeyDown{..}
+                | Event::MouseMotion{..} | Event::MouseButtonDown{..}
+                | Event::MouseWheel{..} => presence.activity(&mut members),
+                _ => {},
+            }
+            // Bindings don't reach past a modal overlay, apart
+            // from the one closing the settings screen
+            let action = match sdlevent {
+                Event::KeyDown{keycode: Some(key), keymod, ..} if !settings.is_capturing() => {
+                    config.action(key, keymod)
+                        .and_then(|v| if !ui_manager.is_modal() || (v == "settings" && settings.is_open()) {
+                            Some(v)
+                        } else {
+                            None
+                        })
+                },
+                _ => None,
+            };
             match sdlevent {
                 Event::TextInput{ref text, ..} => {
                     for c in text.chars() {
@@ -82,10 +169,12 @@ fn main() {
                     ui_manager.mouse_move(x, y);
                 },
                 Event::MouseButtonDown{x, y, mouse_btn, ..} => {
-                    ui_manager.mouse_event::<ui::MouseDownEvent>(
-                        x, y,
-                        ui::MouseClick { button: mouse_btn.into(), x: x, y: y},
-                    );
+                    let click = ui::MouseClick { button: mouse_btn.into(), x: x, y: y};
+                    if mouse_btn != sdl2::mouse::MouseButton::Right
+                        || !ui_manager.mouse_event::<ui::ContextMenuEvent>(x, y, click)
+                    {
+                        ui_manager.mouse_event::<ui::MouseDownEvent>(x, y, click);
+                    }
                 }
                 Event::MouseButtonUp{x, y, mouse_btn, ..} => {
                     ui_manager.mouse_event::<ui::MouseUpEvent>(
@@ -93,22 +182,53 @@ fn main() {
                         ui::MouseClick { button: mouse_btn.into(), x: x, y: y},
                     );
                 }
-                Event::MouseWheel{y, ..} => {
+                Event::MouseWheel{x, y, ..} => {
                     ui_manager.mouse_event::<ui::MouseScrollEvent>(
                         mouse_pos.0,
                         mouse_pos.1,
                         ui::MouseScroll {
                             x: mouse_pos.0,
                             y: mouse_pos.1,
-                            scroll_amount: y
+                            scroll_amount: y,
+                            scroll_amount_x: x,
                         },
                     );
                 },
-                Event::KeyDown{scancode: Some(sdl2::keyboard::Scancode::Grave), ..} => {
-                    ui_manager.load_styles("base");
+                Event::Window{win_event: WindowEvent::Maximized, ..} => {
+                    config.window.maximized = true;
                 },
-                Event::KeyUp{scancode: Some(sdl2::keyboard::Scancode::Grave), ..} => {
-
+                Event::Window{win_event: WindowEvent::Restored, ..} => {
+                    config.window.maximized = false;
+                },
+                Event::KeyDown{keycode: Some(key), keymod, ..} if settings.is_capturing() => {
+                    config_changed |= settings.capture(&mut config, key, keymod);
+                },
+                Event::KeyDown{..} if action.is_some() => {
+                    match action {
+                        Some("settings") => if settings.is_open() {
+                            settings.close(&mut ui_manager);
+                            if let Some(textbox) = query!(root, message_box > textbox).next() {
+                                ui_manager.focus_node(textbox);
+                            }
+                        } else {
+                            settings.open(&mut ui_manager, &config);
+                        },
+                        Some("search") => if let Some(search) = query!(root, channel_bar > search).next() {
+                            ui_manager.focus_node(search);
+                        },
+                        Some("toggle_members") => channel_bar.toggle_members(),
+                        Some("mark_read") => {
+                            let channel = chat.channel().to_owned();
+                            chat.mark_read(&channel);
+                        },
+                        Some("reload_styles") => apply_styles(&mut ui_manager, &config),
+                        _ => {},
+                    }
+                },
+                Event::KeyDown{keycode: Some(Keycode::Escape), ..}
+                    if ui_manager.top_overlay().is_some() =>
+                {
+                    ui_manager.dismiss_overlay();
                 },
                 Event::KeyDown{keycode: Some(Keycode::Tab), ..} => {
                     ui_manager.cycle_focus();
@@ -124,12 +244,49 @@ fn main() {
                     });
                 },
                 Event::Quit{..} => {
+                    store::save(&chat, &members, &root);
+                    let geometry = config.window.clone();
+                    // The size to restore to is lost while maximized
+                    if !config.window.maximized {
+                        let ((w, h), (x, y)) = (window.size(), window.position());
+                        config.window.width = w;
+                        config.window.height = h;
+                        config.window.x = Some(x);
+                        config.window.y = Some(y);
+                    }
+                    if config.window != geometry {
+                        config.save();
+                    }
                     return;
                 },
                 _ => {},
             }
         }
 
+        backend.update(delta);
+        for event in backend.events() {
+            match event {
+                backend::Event::Ready(ready) => {
+                    store::reconcile(&mut chat, &mut members, ready);
+                    presence.refresh(&members);
+                    chat_view.set_names(mention_names(&chat, &members));
+                },
+                backend::Event::Disconnected => outbox.disconnected(),
+                backend::Event::Sent{nonce} => outbox.sent(&mut chat, nonce),
+                backend::Event::Rejected{nonce} => outbox.rejected(&mut chat, nonce),
+                backend::Event::Typing{channel, user} => chat.user_typing(&channel, &user),
+                backend::Event::Message{channel, author, content} => {
+                    let colour = members.member(&author)
+                        .map_or("#ffffff", |v| members.colour(v))
+                        .to_owned();
+                    chat.add_message(&channel, &author, &colour, Utc::now(), &content);
+                },
+                backend::Event::VoiceJoined{channel, user} => voice.user_joined(&channel, &user),
+                backend::Event::Speaking{user, speaking} => voice.set_speaking(&user, speaking),
+            }
+        }
+
+        ui_manager.resize(width as i32, height as i32);
         if let Some(r) = ui_manager.update(delta) {
             if last_rect != Some(r) {
                 if !input.is_active() {
@@ -146,7 +303,10 @@ fn main() {
         }
 
         let mut new_focus = None;
-        for event in ui_manager.events() {
+        let mut menu_action = None;
+        // Collected first so that handlers can use the manager
+        let events = ui_manager.events().collect::<Vec<_>>();
+        for event in events {
             let ui::NodeEvent{value, ty, node} = event;
             match (value.as_str(), ty, node) {
                 ("focus", _, node) => {
@@ -213,51 +373,420 @@ fn main() {
                         let mut info: TextboxInfo = node.get_custom_property("$tb_info").unwrap();
                         info.deleting = true;
                         node.set_property("$tb_info", info);
+                    } else if evt.input == Keycode::Escape {
+                        // Handled on key down as overlays take the
+                        // escape key before it gets here
+                        if node.get_property::<i32>("edit_id").is_some() {
+                            chat.cancel_edit();
+                            new_focus = query!(root, message_box > textbox).next();
+                        } else {
+                            chat.cancel_reply();
+                        }
                     }
                 },
                 ("textbox", EventType::KeyUp(evt), node) => {
                     if evt.input == Keycode::Backspace {
-                        let mut info: TextboxInfo = node.get_custom_property("$tb_info").unwrap();
-                        info.deleting = false;
-                        node.set_property("$tb_info", info);
+                        stop_deleting(&node);
                     } else if evt.input == Keycode::Return {
                         let txt = query!(node, @text).next().unwrap();
                         let text = txt.text().unwrap();
                         txt.set_text("");
-                        let messages = query!(root, chat_area > content).next().unwrap();
-
-                        let time = Local::now();
-
-                        let msg = node!{
-                            message {
-                                icon
-                                author {
-                                    @text(time.format(" Today at %H:%M").to_string())
-                                }
-                                content {
-                                    @text(text)
-                                }
+                        if let Some(id) = node.get_property::<i32>("edit_id") {
+                            chat.edit(id as chat::MessageId, &text);
+                            new_focus = query!(root, message_box > textbox).next();
+                        } else if !text.trim().is_empty() {
+                            let id = chat.send(&text);
+                            outbox.push(id);
+                        }
+                    } else if evt.input == Keycode::Up {
+                        let empty = query!(node, @text).next()
+                            .and_then(|v| v.text())
+                            .map_or(true, |v| v.is_empty());
+                        if empty && node.get_property::<i32>("edit_id").is_none() {
+                            chat.start_edit_last();
+                        }
+                    }
+                },
+                ("message_action", EventType::MouseUp(evt), node) => {
+                    if let ui::MouseButton::Left = evt.button {
+                        let action = node.get_property::<String>("action");
+                        if let (Some(action), Some(id)) = (action, chat::render::message_id(&node)) {
+                            match action.as_str() {
+                                "edit" => {
+                                    chat.start_edit(id);
+                                },
+                                "reply" => if chat.start_reply(id) {
+                                    new_focus = query!(root, message_box > textbox).next();
+                                },
+                                "react" => chat_view.toggle_picker(id),
+                                "delete" => new_focus = confirm_delete(&mut ui_manager, id),
+                                "retry" => outbox.retry(&mut chat, id),
+                                _ => {},
                             }
+                        }
+                    }
+                },
+                ("reaction", EventType::MouseUp(evt), node) => {
+                    if let ui::MouseButton::Left = evt.button {
+                        if let Some(id) = chat::render::message_id(&node) {
+                            if node.get_property::<bool>("add").unwrap_or(false) {
+                                chat_view.toggle_picker(id);
+                            } else if let Some(emoji) = node.get_property::<String>("emoji") {
+                                chat.toggle_reaction(id, &emoji);
+                                chat_view.close_picker();
+                            }
+                        }
+                    }
+                },
+                ("emoji_picker", EventType::MouseUp(evt), _) => {
+                    if let ui::MouseButton::Left = evt.button {
+                        let textbox = query!(root, message_box > textbox).next().unwrap();
+                        if emoji_picker.is_open() {
+                            emoji_picker.close(&mut ui_manager);
+                            new_focus = Some(textbox);
+                        } else if let Some(rect) = textbox.render_position() {
+                            new_focus = Some(emoji_picker.open(&mut ui_manager, rect));
+                        }
+                    }
+                },
+                ("emoji_search", EventType::CharInput(evt), _) => {
+                    emoji_picker.input(evt.input);
+                },
+                ("emoji_picker_dismiss", EventType::Dismiss, _) => {
+                    emoji_picker.dismissed();
+                    new_focus = query!(root, message_box > textbox).next();
+                },
+                ("emoji_search", EventType::KeyDown(evt), _) => {
+                    if let Some(emoji) = emoji_picker.key(evt.input) {
+                        insert_emoji(&root, &emoji);
+                        emoji_picker.picked(&emoji);
+                        emoji_picker.close(&mut ui_manager);
+                        new_focus = query!(root, message_box > textbox).next();
+                    }
+                },
+                ("emoji_pick", EventType::MouseUp(evt), node) => {
+                    if let ui::MouseButton::Left = evt.button {
+                        if let Some(emoji) = node.get_property::<String>("emoji") {
+                            insert_emoji(&root, &emoji);
+                            emoji_picker.picked(&emoji);
+                            emoji_picker.close(&mut ui_manager);
+                            new_focus = query!(root, message_box > textbox).next();
+                        }
+                    }
+                },
+                ("context_menu", EventType::KeyDown(evt), _) => {
+                    menu_action = context_menu.key(&mut ui_manager, evt.input);
+                    // A submenu may have opened
+                    new_focus = context_menu.focus();
+                },
+                ("menu_item", EventType::MouseUp(evt), node) => {
+                    // The right button opens the menu so releasing it
+                    // mustn't pick an item
+                    if let ui::MouseButton::Left = evt.button {
+                        menu_action = context_menu.click(&mut ui_manager, &node);
+                        new_focus = context_menu.focus();
+                    }
+                },
+                ("dialog_button", EventType::MouseUp(evt), node) => {
+                    if let ui::MouseButton::Left = evt.button {
+                        let cancel = node.get_property::<bool>("cancel").unwrap_or(false);
+                        ui::dialog::respond(&mut ui_manager, !cancel);
+                    }
+                },
+                ("dialog_key", EventType::KeyUp(evt), node) => {
+                    // Only reached when the button got the key press too
+                    if evt.input == Keycode::Return {
+                        // Enter confirms unless the cancel button is focused
+                        let cancel = node.get_property::<bool>("cancel").unwrap_or(false);
+                        ui::dialog::respond(&mut ui_manager, !cancel);
+                    }
+                },
+                ("dialog_dismiss", EventType::Dismiss, node) => {
+                    ui::dialog::dismissed(&mut ui_manager, &node);
+                },
+                ("delete_message", EventType::Dialog(confirmed), node) => {
+                    if let (true, Some(id)) = (confirmed, chat::render::message_id(&node)) {
+                        chat.delete(id);
+                    }
+                    new_focus = query!(root, message_box > textbox).next();
+                },
+                ("category", EventType::MouseUp(evt), node) => {
+                    if let ui::MouseButton::Left = evt.button {
+                        categories.toggle(&node);
+                    }
+                },
+                ("channel", EventType::MouseUp(evt), node) => {
+                    if let (ui::MouseButton::Left, Some(name)) = (evt.button, node.get_property::<String>("name")) {
+                        if node.get_property::<bool>("voice").unwrap_or(false) {
+                            voice.join(&name);
+                        } else {
+                            chat.set_channel(&name);
+                            new_focus = query!(root, message_box > textbox).next();
+                        }
+                    }
+                },
+                ("pins_button", EventType::MouseUp(evt), node) => {
+                    if let ui::MouseButton::Left = evt.button {
+                        channel_bar.open_pins(&mut ui_manager, &chat, &node);
+                    }
+                },
+                ("members_button", EventType::MouseUp(evt), _) => {
+                    if let ui::MouseButton::Left = evt.button {
+                        channel_bar.toggle_members();
+                    }
+                },
+                ("search_key", EventType::KeyUp(evt), node) => {
+                    if evt.input == Keycode::Backspace {
+                        stop_deleting(&node);
+                    } else if evt.input == Keycode::Return {
+                        channel_bar.search(&chat);
+                    }
+                },
+                ("popout_item", EventType::MouseUp(evt), node) => {
+                    if let (ui::MouseButton::Left, Some(id)) = (evt.button, chat::render::message_id(&node)) {
+                        chat_view.jump_to(id);
+                        channel_bar.close_popout(&mut ui_manager);
+                    }
+                },
+                ("search_result", EventType::MouseUp(evt), node) => {
+                    let channel = chat::render::message_id(&node)
+                        .and_then(|id| chat.message(id))
+                        .map(|v| (v.id, v.channel.clone()));
+                    if let (ui::MouseButton::Left, Some((id, channel))) = (evt.button, channel) {
+                        chat.set_channel(&channel);
+                        chat_view.jump_to(id);
+                    }
+                },
+                ("search_close", EventType::MouseUp(evt), _) => {
+                    if let ui::MouseButton::Left = evt.button {
+                        channel_bar.close_search();
+                    }
+                },
+                ("message_popout_dismiss", EventType::Dismiss, _) => {
+                    channel_bar.popout_dismissed();
+                },
+                ("voice_button", EventType::MouseUp(evt), node) => {
+                    if let ui::MouseButton::Left = evt.button {
+                        voice.button(&node);
+                    }
+                },
+                ("user_profile", EventType::MouseUp(evt), node) => {
+                    if let ui::MouseButton::Left = evt.button {
+                        if let Some(name) = query!(node, name > @text).next().and_then(|v| v.text()) {
+                            show_profile(&mut ui_manager, &mut profile_popout, &members, &root, &name, &node);
+                        }
+                    }
+                },
+                ("author_profile", EventType::MouseUp(evt), node) => {
+                    if let (ui::MouseButton::Left, Some(name)) = (evt.button, node.text()) {
+                        show_profile(&mut ui_manager, &mut profile_popout, &members, &root, &name, &node);
+                    }
+                },
+                ("profile_message", EventType::MouseUp(evt), _) => {
+                    if let ui::MouseButton::Left = evt.button {
+                        if let Some(user) = profile_popout.user().map(|v| v.to_owned()) {
+                            directs_changed |= open_direct_message(&root, &mut chat, &user);
+                        }
+                        profile_popout.close(&mut ui_manager);
+                        for user in query!(root, user_list > user) {
+                            user.set_property("selected", false);
+                        }
+                    }
+                },
+                ("status_bar", EventType::MouseUp(evt), _) => {
+                    if let ui::MouseButton::Left = evt.button {
+                        new_focus = presence.open_picker(&mut ui_manager, &members);
+                    }
+                },
+                ("settings_button", EventType::MouseUp(evt), _) => {
+                    if let ui::MouseButton::Left = evt.button {
+                        settings.open(&mut ui_manager, &config);
+                    }
+                },
+                ("settings_option", EventType::MouseUp(evt), node) => {
+                    if let ui::MouseButton::Left = evt.button {
+                        config_changed |= settings.pick(&mut config, &node);
+                    }
+                },
+                ("settings_close", EventType::MouseUp(evt), _) => {
+                    if let ui::MouseButton::Left = evt.button {
+                        settings.close(&mut ui_manager);
+                        new_focus = query!(root, message_box > textbox).next();
+                    }
+                },
+                ("settings_dismiss", EventType::Dismiss, _) => {
+                    settings.dismissed();
+                    new_focus = query!(root, message_box > textbox).next();
+                },
+                ("status_option", EventType::MouseUp(evt), node) => {
+                    if let ui::MouseButton::Left = evt.button {
+                        presence.pick(&mut ui_manager, &mut members, &node);
+                        new_focus = query!(root, message_box > textbox).next();
+                    }
+                },
+                ("status_custom", EventType::KeyUp(evt), node) => {
+                    if evt.input == Keycode::Backspace {
+                        stop_deleting(&node);
+                    } else if evt.input == Keycode::Return {
+                        presence.save_custom(&mut ui_manager, &mut members);
+                        new_focus = query!(root, message_box > textbox).next();
+                    }
+                },
+                ("status_picker_dismiss", EventType::Dismiss, _) => {
+                    presence.picker_dismissed();
+                    new_focus = query!(root, message_box > textbox).next();
+                },
+                ("profile_dismiss", EventType::Dismiss, _) => {
+                    profile_popout.dismissed();
+                    for user in query!(root, user_list > user) {
+                        user.set_property("selected", false);
+                    }
+                },
+                ("context_menu_dismiss", EventType::Dismiss, node) => {
+                    new_focus = context_menu.dismissed(&mut ui_manager, &node)
+                        .or_else(|| query!(root, message_box > textbox).next());
+                },
+                (kind, EventType::ContextMenu(evt), node) => {
+                    let items = match kind {
+                        "message_context" => message_menu(&chat, &node),
+                        "user_context" => user_menu(),
+                        "channel_context" => channel_menu(&chat, &node),
+                        _ => Vec::new(),
+                    };
+                    if !items.is_empty() {
+                        new_focus = Some(context_menu.open(
+                            &mut ui_manager,
+                            node, items,
+                            evt.x, evt.y,
+                            (width as i32, height as i32),
+                        ));
+                    }
+                },
+                ("cancel_reply", EventType::MouseUp(evt), _) => {
+                    if let ui::MouseButton::Left = evt.button {
+                        chat.cancel_reply();
+                    }
+                },
+                ("reply_jump", EventType::MouseUp(evt), node) => {
+                    if let ui::MouseButton::Left = evt.button {
+                        if let Some(id) = chat::render::message_id(&node) {
+                            chat_view.jump_to(id);
+                        }
+                    }
+                },
+                ("highlight", EventType::Update(delta), node) => {
+                    let timer = node.get_property::<f64>("highlight_timer").unwrap_or(0.0) - delta;
+                    if timer <= 0.0 {
+                        node.set_property("highlight", false);
+                    }
+                    node.set_property("highlight_timer", timer);
+                },
+                ("chat_scroll", EventType::MouseScroll(evt), _) => {
+                    chat_view.scroll_by(evt.scroll_amount * 40);
+                },
+                ("code_scroll", EventType::MouseScroll(evt), node) => {
+                    use std::cmp;
+                    let max = markdown::code_max_scroll(&node);
+                    if evt.scroll_amount_x == 0 && max == 0 {
+                        // Nothing to scroll so the wheel scrolls the chat
+                        chat_view.scroll_by(evt.scroll_amount * 40);
+                    } else {
+                        // Only horizontal scrolling is needed but not every
+                        // mouse has a way to do it, so fall back to the wheel.
+                        let amount = if evt.scroll_amount_x != 0 {
+                            -evt.scroll_amount_x
+                        } else {
+                            evt.scroll_amount
                         };
-                        let author = query!(msg, author).next().unwrap();
-                        author.add_child_first({
-                            let t = ui::Node::new_text("Rust User");
-                            t.set_property("col", "#FFBF00".to_owned());
-                            t
-                        });
-                        messages.add_child(msg);
+                        let scroll = node.get_property::<i32>("scroll").unwrap_or(0) - amount * 30;
+                        node.set_property("scroll", cmp::max(0, cmp::min(max, scroll)));
+                    }
+                },
+                ("link", EventType::MouseUp(evt), node) => {
+                    if let ui::MouseButton::Left = evt.button {
+                        if let Some(link) = markdown::Link::from_node(&node) {
+                            open_link(&mut ui_manager, &mut profile_popout, &members, &mut chat, &root, link, &node);
+                        }
                     }
                 },
+                ("typing", EventType::Update(delta), _) => {
+                    chat.update_typing(delta);
+                },
+                ("spoiler", EventType::MouseUp(evt), node) => {
+                    if let ui::MouseButton::Left = evt.button {
+                        node.set_property("revealed", true);
+                    }
+                },
+                ("game_tooltip", EventType::Tooltip, node) => {
+                    // The name may be cut off in the user list
+                    let text = query!(node, @text)
+                        .filter_map(|v| v.text())
+                        .collect::<String>();
+                    ui_manager.show_tooltip(&node, &text);
+                },
                 ("textbox", EventType::CharInput(evt), node) => {
                     let txt = query!(node, @text).next().unwrap();
                     let mut text = txt.text().unwrap();
                     text.push(evt.input);
                     txt.set_text(text);
+                    let is_message_box = query!(root, message_box > textbox).next()
+                        .map_or(false, |v| v.is_same(&node));
+                    if is_message_box && chat.typed() {
+                        backend.send(backend::Request::Typing {
+                            channel: chat.channel().to_owned(),
+                        });
+                    }
                 }
                 event => println!("{:?} {:?}", event.0, event.1),
             }
         }
 
+        if let (Some(action), Some(target)) = (menu_action, context_menu.target()) {
+            let focus = if action == "profile" {
+                let name = query!(target, name > @text).next().and_then(|v| v.text());
+                if let Some(name) = name {
+                    show_profile(&mut ui_manager, &mut profile_popout, &members, &root, &name, &target);
+                }
+                None
+            } else if action == "retry" {
+                if let Some(id) = chat::render::message_id(&target) {
+                    outbox.retry(&mut chat, id);
+                }
+                None
+            } else {
+                run_menu_action(&action, &target, &mut ui_manager, &mut chat, &root, &clipboard)
+            };
+            new_focus = focus.or_else(|| query!(root, message_box > textbox).next());
+        }
+
+        if config_changed {
+            config.save();
+            apply_styles(&mut ui_manager, &config);
+            let time_format = config.timestamps.format();
+            chat_view.set_format(time_format);
+            channel_bar.set_format(time_format);
+        }
+
+        if let Some(edit) = chat_view.update(&mut chat) {
+            new_focus = Some(edit);
+        }
+        categories.update();
+        channel_bar.update(&chat);
+        outbox.update(&chat, &mut backend);
+        presence.update(&mut members, delta);
+        save_timer -= delta;
+        if save_timer <= 0.0 {
+            save_timer = store::SAVE_INTERVAL;
+            // Both flags need clearing
+            let modified = chat.take_modified() | members.take_modified();
+            if modified || directs_changed {
+                store::save(&chat, &members, &root);
+                directs_changed = false;
+            }
+        }
+        voice.update(delta);
+        member_list.update(&mut members);
+
         if let Some(focus) = new_focus {
             ui_manager.focus_node(focus);
         }
@@ -266,11 +795,11 @@ fn main() {
         ui_renderer.render(&mut *ui_manager.manager.borrow_mut(), width, height);
 
         window.gl_swap_window();
-        // Keep the game at our target fps. TODO: Make config option
+        // Keep the game at the target fps
         let frame_time = start.elapsed();
-        let target = 60;
+        let target = config.frame_cap;
 
-        if target != i32::max_value() as u32 {
+        if target != 0 {
             let target_frame_time = Duration::from_secs(1) / target;
             if frame_time < target_frame_time {
                 thread::sleep(target_frame_time - frame_time);
@@ -279,6 +808,320 @@ fn main() {
     }
 }
 
+/// Loads the styles for the theme with the text scaled
+/// as set in the config
+fn apply_styles(ui_manager: &mut ui::Manager, config: &config::Config) {
+    ui_manager.set_font_scale(config.font_scale);
+    ui_manager.load_styles("base");
+    for &theme in config::THEMES {
+        if let Some(styles) = theme.styles() {
+            if theme == config.theme {
+                ui_manager.load_styles(styles);
+            } else {
+                ui_manager.unload_styles(styles);
+            }
+        }
+    }
+}
+
+/// The names of the users that messages can mention
+fn mention_names(chat: &chat::Chat, members: &members::Members) -> Vec<String> {
+    let mut names = members.members().iter()
+        .map(|v| v.name.clone())
+        .collect::<Vec<_>>();
+    if !names.contains(&chat.user) {
+        names.push(chat.user.clone());
+    }
+    names
+}
+
+/// Asks the user to confirm deleting the message.
+///
+/// Returns the node in the dialog to focus.
+fn confirm_delete(ui_manager: &mut ui::Manager, id: chat::MessageId) -> Option<ui::Node> {
+    let dialog = ui::dialog::Dialog::new("delete_message", "Delete Message")
+        .message("Are you sure you want to delete this message? This can't be undone.")
+        .confirm("Delete")
+        .danger()
+        .open(ui_manager);
+    dialog.set_property("msg_id", id as i32);
+    ui::dialog::focus(&dialog)
+}
+
+/// Builds the context menu for a message
+fn message_menu(chat: &chat::Chat, node: &ui::Node) -> Vec<ui::menu::MenuItem> {
+    use ui::menu::MenuItem;
+    let msg = if let Some(msg) = chat::render::message_id(node).and_then(|id| chat.message(id)) {
+        msg
+    } else {
+        return Vec::new();
+    };
+    // Only the text of messages the server doesn't have
+    // yet can be used
+    if msg.state != chat::SendState::Sent {
+        let mut items = vec![
+            MenuItem::action("Copy Text", "copy"),
+            MenuItem::Separator,
+        ];
+        if msg.state == chat::SendState::Failed {
+            items.push(MenuItem::action("Retry Sending", "retry"));
+        }
+        items.push(MenuItem::danger("Delete Message", "delete"));
+        return items;
+    }
+    let mut items = vec![
+        MenuItem::action("Copy Text", "copy"),
+        MenuItem::action("Reply", "reply"),
+        MenuItem::submenu("Add Reaction", emoji::EMOJI.iter()
+            .filter(|v| v.category == emoji::Category::People)
+            .take(8)
+            .map(|v| MenuItem::action(&format!("{}  :{}:", v.emoji, v.name), &format!("react:{}", v.emoji)))
+            .collect()),
+    ];
+    items.push(if msg.pinned {
+        MenuItem::action("Unpin Message", "unpin")
+    } else {
+        MenuItem::action("Pin Message", "pin")
+    });
+    if chat.is_own(msg) {
+        items.push(MenuItem::Separator);
+        items.push(MenuItem::action("Edit Message", "edit"));
+        items.push(MenuItem::danger("Delete Message", "delete"));
+    }
+    items
+}
+
+/// Builds the context menu for a user in the user list
+fn user_menu() -> Vec<ui::menu::MenuItem> {
+    use ui::menu::MenuItem;
+    vec![
+        MenuItem::action("Profile", "profile"),
+        MenuItem::action("Mention", "mention"),
+    ]
+}
+
+/// Builds the context menu for a channel in the channel list
+fn channel_menu(chat: &chat::Chat, node: &ui::Node) -> Vec<ui::menu::MenuItem> {
+    use ui::menu::MenuItem;
+    let name = if let Some(name) = node.get_property::<String>("name") {
+        name
+    } else {
+        return Vec::new();
+    };
+    vec![
+        if chat.is_unread(&name) {
+            MenuItem::action("Mark As Read", "mark_read")
+        } else {
+            MenuItem::disabled("Mark As Read")
+        },
+        MenuItem::Separator,
+        if chat.is_muted(&name) {
+            MenuItem::action("Unmute Channel", "unmute")
+        } else {
+            MenuItem::action("Mute Channel", "mute")
+        },
+    ]
+}
+
+/// Performs the action picked from a context menu opened
+/// for the target node.
+///
+/// Returns the node that should be focused afterwards if any.
+fn run_menu_action(
+    action: &str, target: &ui::Node,
+    ui_manager: &mut ui::Manager,
+    chat: &mut chat::Chat,
+    root: &ui::Node,
+    clipboard: &sdl2::clipboard::ClipboardUtil,
+) -> Option<ui::Node> {
+    let textbox = query!(root, message_box > textbox).next();
+    let (action, arg) = match action.find(':') {
+        Some(pos) => (&action[..pos], &action[pos + 1..]),
+        None => (action, ""),
+    };
+    let user = query!(target, name > @text).next().and_then(|v| v.text());
+    match (action, chat::render::message_id(target)) {
+        ("copy", Some(id)) => {
+            if let Some(msg) = chat.message(id) {
+                if let Err(err) = clipboard.set_clipboard_text(&msg.content) {
+                    println!("Failed to copy message: {}", err);
+                }
+            }
+        },
+        ("reply", Some(id)) => if chat.start_reply(id) {
+            return textbox;
+        },
+        ("react", Some(id)) => {
+            chat.toggle_reaction(id, arg);
+        },
+        ("edit", Some(id)) => {
+            chat.start_edit(id);
+        },
+        ("delete", Some(id)) => return confirm_delete(ui_manager, id),
+        ("pin", Some(id)) => {
+            chat.set_pinned(id, true);
+        },
+        ("unpin", Some(id)) => {
+            chat.set_pinned(id, false);
+        },
+        ("mention", _) => if let Some(user) = user {
+            if let Some(txt) = query!(root, message_box > textbox > content > @text).next() {
+                let mut text = txt.text().unwrap_or_default();
+                if !text.is_empty() && !text.ends_with(' ') {
+                    text.push(' ');
+                }
+                text.push_str(&format!("@{} ", user));
+                txt.set_text(text);
+            }
+            return textbox;
+        },
+        ("mark_read", _) => if let Some(name) = target.get_property::<String>("name") {
+            chat.mark_read(&name);
+        },
+        ("mute", _) | ("unmute", _) => if let Some(name) = target.get_property::<String>("name") {
+            chat.set_muted(&name, action == "mute");
+        },
+        _ => {},
+    }
+    None
+}
+
+/// Inserts the emoji at the caret of the message textbox
+fn insert_emoji(root: &ui::Node, emoji: &str) {
+    // The caret is always at the end of the text currently
+    if let Some(txt) = query!(root, message_box > textbox > content > @text).next() {
+        let mut text = txt.text().unwrap_or_default();
+        text.push_str(emoji);
+        txt.set_text(text);
+    }
+}
+
+/// Handles a link in a message being clicked
+fn open_link(
+    ui_manager: &mut ui::Manager,
+    profile_popout: &mut profile::ProfilePopout,
+    members: &members::Members,
+    chat: &mut chat::Chat,
+    root: &ui::Node,
+    link: markdown::Link,
+    node: &ui::Node,
+) {
+    use markdown::Link;
+    match link {
+        Link::Url(url) => {
+            if let Err(err) = std::process::Command::new("xdg-open")
+                .arg(&url)
+                .spawn()
+            {
+                println!("Failed to open {:?}: {}", url, err);
+            }
+        },
+        Link::User(name) => show_profile(ui_manager, profile_popout, members, root, &name, node),
+        Link::Channel(name) => {
+            // `#word` is linked whether or not the channel exists
+            let exists = query!(root, channel_list > channels > channel)
+                .filter(|v| !v.get_property::<bool>("voice").unwrap_or(false))
+                .any(|v| v.get_property::<String>("name").map_or(false, |v| v == name));
+            if exists {
+                chat.set_channel(&name);
+            }
+        },
+        Link::Emoji(_) => {},
+    }
+}
+
+/// Opens the profile popout for the user next to the anchor
+/// node, or closes it if its already showing them
+fn show_profile(
+    ui_manager: &mut ui::Manager,
+    profile_popout: &mut profile::ProfilePopout,
+    members: &members::Members,
+    root: &ui::Node,
+    name: &str,
+    anchor: &ui::Node,
+) {
+    let user = query!(root, user_list > user)
+        .find(|v| query!(v, name > @text).next()
+            .and_then(|v| v.text())
+            .map_or(false, |v| v.eq_ignore_ascii_case(name)));
+    let showing = profile_popout.user()
+        .map_or(false, |v| v.eq_ignore_ascii_case(name));
+    for other in query!(root, user_list > user) {
+        other.set_property("selected", false);
+    }
+    if showing {
+        profile_popout.close(ui_manager);
+        return;
+    }
+    let rect = if let Some(rect) = anchor.render_position() {
+        rect
+    } else {
+        return;
+    };
+    let profile = members.member(name)
+        .map(|v| profile::Profile::from_member(members, v))
+        .unwrap_or_else(|| profile::Profile::unknown(
+            name,
+            &anchor.get_property::<String>("col").unwrap_or_else(|| "#ffffff".to_owned()),
+        ));
+    if let Some(user) = user {
+        user.set_property("selected", true);
+    }
+    profile_popout.open(ui_manager, &profile, rect);
+}
+
+/// Selects the direct message channel for the user, adding
+/// it to the channel list if it doesn't exist yet.
+///
+/// Returns whether the channel was added.
+fn open_direct_message(root: &ui::Node, chat: &mut chat::Chat, user: &str) -> bool {
+    let (name, added) = add_direct_message(root, user);
+    chat.set_channel(&name);
+    added
+}
+
+/// Adds the direct message channel for the user to the
+/// channel list if it isn't there yet.
+///
+/// Returns the name of the channel and whether it was added.
+fn add_direct_message(root: &ui::Node, user: &str) -> (String, bool) {
+    let name = format!("@{}", user);
+    let channels = query!(root, channel_list > channels).next().unwrap();
+    let exists = query!(channels, channel)
+        .any(|v| v.get_property::<String>("name").map_or(false, |v| v == name));
+    if !exists {
+        let has_header = query!(channels, header > @text)
+            .any(|v| v.text().map_or(false, |v| v == "DIRECT MESSAGES"));
+        if !has_header {
+            let header = node!{
+                header {
+                    @text("DIRECT MESSAGES")
+                }
+            };
+            header.set_property("category", "direct".to_owned());
+            channels.add_child(header);
+        }
+        let channel = node!{
+            channel {
+                @text(name.clone())
+            }
+        };
+        channel.set_property("name", name.clone());
+        channel.set_property("category", "direct".to_owned());
+        channels.add_child(channel);
+    }
+    (name, !exists)
+}
+
+/// Stops the repeated deleting started by holding backspace
+/// in a textbox
+fn stop_deleting(node: &ui::Node) {
+    if let Some(mut info) = node.get_custom_property::<TextboxInfo>("$tb_info") {
+        info.deleting = false;
+        node.set_property("$tb_info", info);
+    }
+}
+
 #[derive(Clone)]
 struct TextboxInfo {
     backspace_timer: f64,
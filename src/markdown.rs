@@ -0,0 +1,607 @@
+//! Discord flavoured markdown parsing for message content.
+//!
+//! Messages are split into blocks (plain text, quotes and code) made up
+//! of styled spans which are then turned into `@text` nodes with
+//! the same properties the hand written messages in `main.desc` use.
+//!
+//! Names can contain spaces so `@` mentions are matched against
+//! the names of the users that are known instead of ending at
+//! the first space.
+
+use ui;
+use highlight;
+use emoji;
+
+/// The styling applied to a span of text
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Style {
+    pub bold: bool,
+    pub italic: bool,
+    pub underline: bool,
+    pub strike: bool,
+    pub mono: bool,
+    pub spoiler: bool,
+}
+
+/// A run of text sharing the same style
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Span {
+    pub text: String,
+    pub style: Style,
+    pub link: Option<Link>,
+}
+
+/// A clickable token within a message
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Link {
+    /// A `http://` or `https://` url
+    Url(String),
+    /// A `@user` mention
+    User(String),
+    /// A `#channel` reference
+    Channel(String),
+    /// A `:emoji:` shortcode
+    Emoji(String),
+}
+
+impl Link {
+    fn kind(&self) -> &'static str {
+        match *self {
+            Link::Url(_) => "url",
+            Link::User(_) => "user",
+            Link::Channel(_) => "channel",
+            Link::Emoji(_) => "emoji",
+        }
+    }
+
+    fn target(&self) -> &str {
+        match *self {
+            Link::Url(ref v)
+            | Link::User(ref v)
+            | Link::Channel(ref v)
+            | Link::Emoji(ref v) => v,
+        }
+    }
+
+    /// Returns the link a node created by `span_node` refers to
+    pub fn from_node(node: &ui::Node) -> Option<Link> {
+        let target = if let Some(target) = node.get_property::<String>("target") {
+            target
+        } else {
+            return None;
+        };
+        match node.get_property::<String>("link").as_ref().map(|v| v.as_str()) {
+            Some("url") => Some(Link::Url(target)),
+            Some("user") => Some(Link::User(target)),
+            Some("channel") => Some(Link::Channel(target)),
+            Some("emoji") => Some(Link::Emoji(target)),
+            _ => None,
+        }
+    }
+}
+
+/// A section of a message
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Block {
+    /// Regular text
+    Text(Vec<Span>),
+    /// Text prefixed with `> ` or following `>>> `
+    Quote(Vec<Span>),
+    /// A fenced code block with an optional language
+    Code {
+        lang: Option<String>,
+        code: String,
+    },
+}
+
+/// Inline markers in the order they are tried. Longer markers
+/// must come before their prefixes.
+const MARKERS: &[(&str, fn(Style) -> Style)] = &[
+    ("`", mono),
+    ("***", bold_italic),
+    ("**", bold),
+    ("___", underline_italic),
+    ("__", underline),
+    ("~~", strike),
+    ("||", spoiler),
+    ("*", italic),
+    ("_", italic),
+];
+
+fn mono(s: Style) -> Style { Style { mono: true, .. s } }
+fn bold(s: Style) -> Style { Style { bold: true, .. s } }
+fn italic(s: Style) -> Style { Style { italic: true, .. s } }
+fn bold_italic(s: Style) -> Style { Style { bold: true, italic: true, .. s } }
+fn underline(s: Style) -> Style { Style { underline: true, .. s } }
+fn underline_italic(s: Style) -> Style { Style { underline: true, italic: true, .. s } }
+fn strike(s: Style) -> Style { Style { strike: true, .. s } }
+fn spoiler(s: Style) -> Style { Style { spoiler: true, .. s } }
+
+/// Parses the message into a list of blocks, linking mentions
+/// of the users in `names`
+pub fn parse(src: &str, names: &[String]) -> Vec<Block> {
+    let mut blocks = Vec::new();
+    let mut rest = src;
+    while let Some(start) = rest.find("```") {
+        let after = &rest[start + 3..];
+        let end = if let Some(end) = after.find("```") {
+            end
+        } else {
+            break
+        };
+        parse_text(&rest[..start], names, &mut blocks);
+        blocks.push(parse_code(&after[..end]));
+        rest = &after[end + 3..];
+    }
+    parse_text(rest, names, &mut blocks);
+    blocks
+}
+
+fn parse_code(src: &str) -> Block {
+    // The language is the first line when the code continues after it.
+    // The textbox is single line so also accept a leading word when
+    // its a language we can highlight.
+    let (lang, code) = if let Some(nl) = src.find('\n') {
+        let first = src[..nl].trim();
+        if !first.is_empty() && !first.contains(char::is_whitespace) {
+            (Some(first), &src[nl + 1..])
+        } else {
+            (None, src)
+        }
+    } else {
+        match src.find(' ') {
+            Some(sp) if highlight::is_supported(&src[..sp]) => (Some(&src[..sp]), &src[sp + 1..]),
+            _ => (None, src),
+        }
+    };
+    Block::Code {
+        lang: lang.map(|v| v.to_owned()),
+        code: code.trim_matches('\n').to_owned(),
+    }
+}
+
+fn parse_text(src: &str, names: &[String], blocks: &mut Vec<Block>) {
+    let mut text = String::new();
+    let mut quote = String::new();
+
+    let mut lines = src.lines();
+    while let Some(line) = lines.next() {
+        if line.starts_with(">>> ") || line == ">>>" {
+            // Everything after a `>>>` is quoted
+            if !quote.is_empty() {
+                quote.push('\n');
+            }
+            quote.push_str(&line[3..].trim_left());
+            for line in &mut lines {
+                quote.push('\n');
+                quote.push_str(line);
+            }
+        } else if line.starts_with("> ") || line == ">" {
+            flush(blocks, names, &mut text, Block::Text);
+            if !quote.is_empty() {
+                quote.push('\n');
+            }
+            quote.push_str(&line[1..].trim_left());
+            continue;
+        } else {
+            flush(blocks, names, &mut quote, Block::Quote);
+            if !text.is_empty() {
+                text.push('\n');
+            }
+            text.push_str(line);
+            continue;
+        }
+        // Only reached by `>>>` which consumes the rest of the message
+        flush(blocks, names, &mut text, Block::Text);
+    }
+    flush(blocks, names, &mut text, Block::Text);
+    flush(blocks, names, &mut quote, Block::Quote);
+}
+
+fn flush<F>(blocks: &mut Vec<Block>, names: &[String], text: &mut String, block: F)
+    where F: FnOnce(Vec<Span>) -> Block
+{
+    if text.is_empty() {
+        return;
+    }
+    let mut spans = Vec::new();
+    parse_inline(text, names, Style::default(), &mut spans);
+    text.clear();
+    blocks.push(block(spans));
+}
+
+/// Characters that can be escaped with a backslash
+const ESCAPABLE: &str = "\\*_~`|>";
+
+/// Parses the inline styling markers of the text, appending
+/// the resulting spans to `out`
+pub fn parse_inline(src: &str, names: &[String], style: Style, out: &mut Vec<Span>) {
+    let mut text = String::new();
+    let mut rest = src;
+    // Whether a url could start here, matching `push_span`
+    let mut boundary = true;
+    'outer: while !rest.is_empty() {
+        if boundary && is_url(rest) {
+            // Markers are common in urls so they are left alone
+            // for `push_span` to link
+            let len = rest.find(char::is_whitespace).unwrap_or(rest.len());
+            text.push_str(&rest[..len]);
+            rest = &rest[len..];
+            boundary = false;
+            continue;
+        }
+        if rest.starts_with('\\') {
+            let mut chars = rest[1..].chars();
+            if let Some(c) = chars.next() {
+                if ESCAPABLE.contains(c) {
+                    text.push(c);
+                    rest = chars.as_str();
+                    boundary = false;
+                    continue;
+                }
+            }
+        }
+        for &(marker, apply) in MARKERS {
+            if !rest.starts_with(marker) {
+                continue;
+            }
+            let after = &rest[marker.len()..];
+            let end = if let Some(end) = after.find(marker) {
+                end
+            } else {
+                continue;
+            };
+            let inner = &after[..end];
+            // `2 * 3 * 4` shouldn't become italic
+            if inner.is_empty()
+                || (marker != "`" && (inner.starts_with(char::is_whitespace)
+                    || inner.ends_with(char::is_whitespace)))
+            {
+                continue;
+            }
+
+            push_span(out, names, &mut text, style);
+            let inner_style = apply(style);
+            if inner_style.mono && !style.mono {
+                // Code spans don't nest any other styling
+                out.push(Span {
+                    text: inner.to_owned(),
+                    style: inner_style,
+                    link: None,
+                });
+            } else {
+                parse_inline(inner, names, inner_style, out);
+            }
+            rest = &after[end + marker.len()..];
+            continue 'outer;
+        }
+        let c = rest.chars().next().unwrap();
+        boundary = c.is_whitespace() || c == '(';
+        text.push(c);
+        rest = &rest[c.len_utf8()..];
+    }
+    push_span(out, names, &mut text, style);
+}
+
+fn push_span(out: &mut Vec<Span>, names: &[String], text: &mut String, style: Style) {
+    if text.is_empty() {
+        return;
+    }
+    let text = ::std::mem::replace(text, String::new());
+    if style.mono {
+        out.push(Span {
+            text: text,
+            style: style,
+            link: None,
+        });
+        return;
+    }
+
+    // Split out any links in the text into their own spans
+    let mut plain = String::new();
+    let mut rest = text.as_str();
+    let mut boundary = true;
+    while let Some(c) = rest.chars().next() {
+        if boundary {
+            if let Some((link, len)) = find_link(rest, names) {
+                if !plain.is_empty() {
+                    out.push(Span {
+                        text: ::std::mem::replace(&mut plain, String::new()),
+                        style: style,
+                        link: None,
+                    });
+                }
+                let display = match link {
+                    Link::Emoji(ref name) => emoji::lookup(name).unwrap().to_owned(),
+                    _ => rest[..len].to_owned(),
+                };
+                out.push(Span {
+                    text: display,
+                    style: style,
+                    link: Some(link),
+                });
+                rest = &rest[len..];
+                boundary = false;
+                continue;
+            }
+        }
+        boundary = c.is_whitespace() || c == '(';
+        plain.push(c);
+        rest = &rest[c.len_utf8()..];
+    }
+    if !plain.is_empty() {
+        out.push(Span {
+            text: plain,
+            style: style,
+            link: None,
+        });
+    }
+}
+
+fn is_url(src: &str) -> bool {
+    src.starts_with("http://") || src.starts_with("https://")
+}
+
+fn is_name_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '-'
+}
+
+/// Returns the link at the start of the text and its length
+fn find_link(src: &str, names: &[String]) -> Option<(Link, usize)> {
+    if is_url(src) {
+        let len = src.find(char::is_whitespace).unwrap_or(src.len());
+        // Trailing punctuation is most likely part of the sentence
+        let url = src[..len].trim_right_matches(|c: char| ".,:;!?)'\"".contains(c));
+        return Some((Link::Url(url.to_owned()), url.len()));
+    }
+    if src.starts_with('@') {
+        return find_mention(&src[1..], names)
+            .map(|name| (Link::User(name.to_owned()), 1 + name.len()));
+    }
+    let first = match src.chars().next() {
+        Some(c) if c == '#' || c == ':' => c,
+        _ => return None,
+    };
+    let name_len = src[1..].find(|c: char| !(is_name_char(c) || (first == ':' && c == '+')))
+        .unwrap_or(src.len() - 1);
+    let name = &src[1..1 + name_len];
+    if name.is_empty() {
+        return None;
+    }
+    match first {
+        '#' => Some((Link::Channel(name.to_owned()), 1 + name_len)),
+        _ if src[1 + name_len..].starts_with(':') && emoji::lookup(name).is_some() => {
+            Some((Link::Emoji(name.to_owned()), 2 + name_len))
+        },
+        _ => None,
+    }
+}
+
+/// Returns the longest of the names that the text starts with
+/// as a whole word
+fn find_mention<'a>(src: &str, names: &'a [String]) -> Option<&'a str> {
+    names.iter()
+        .filter(|v| !v.is_empty() && src.starts_with(v.as_str())
+            && !src[v.len()..].starts_with(is_name_char))
+        .map(|v| v.as_str())
+        .max_by_key(|v| v.len())
+}
+
+/// Creates a text node for the span with the matching
+/// style properties set
+pub fn span_node(span: &Span) -> ui::Node {
+    let t = ui::Node::new_text(span.text.clone());
+    let style = span.style;
+    if style.bold {
+        t.set_property("bold", true);
+    }
+    if style.italic {
+        t.set_property("italic", true);
+    }
+    if style.underline {
+        t.set_property("underline", true);
+    }
+    if style.strike {
+        t.set_property("strike", true);
+    }
+    if style.mono {
+        t.set_property("mono", true);
+    }
+    if style.spoiler {
+        t.set_property("spoiler", true);
+    }
+    if let Some(link) = span.link.as_ref() {
+        t.set_property("link", link.kind().to_owned());
+        t.set_property("target", link.target().to_owned());
+    }
+    t
+}
+
+/// Parses the message and adds the styled nodes to the
+/// passed content node
+pub fn build_content(content: &ui::Node, src: &str, names: &[String]) {
+    for block in parse(src, names) {
+        match block {
+            Block::Text(spans) => for span in &spans {
+                content.add_child(span_node(span));
+            },
+            Block::Quote(spans) => {
+                let quote = node!(quote);
+                for span in &spans {
+                    quote.add_child(span_node(span));
+                }
+                content.add_child(quote);
+            },
+            Block::Code { lang, code } => {
+                content.add_child(code_block_node(lang.as_ref().map(|v| v.as_str()), &code));
+            },
+        }
+    }
+}
+
+/// Creates a `code_block` node for the code, highlighting
+/// it if the language is supported
+pub fn code_block_node(lang: Option<&str>, code: &str) -> ui::Node {
+    use std::cmp;
+    let lines = lang.and_then(|l| highlight::highlight(l, code))
+        .unwrap_or_else(|| code.lines()
+            .map(|l| vec![(highlight::Kind::Plain, l.to_owned())])
+            .collect());
+
+    let block = node!(code_block);
+    let inner = node!(code);
+    for line in &lines {
+        let node = node!(line);
+        for &(kind, ref text) in line {
+            let t = ui::Node::new_text(text.clone());
+            if let Some(col) = kind.colour() {
+                t.set_property("col", col.to_owned());
+            }
+            node.add_child(t);
+        }
+        inner.add_child(node);
+    }
+    block.add_child(inner);
+    block.set_property("lines", cmp::max(1, lines.len()) as i32);
+    block
+}
+
+/// Returns how far the code block can be scrolled sideways,
+/// zero when every line fits or it hasn't been laid out yet
+pub fn code_max_scroll(block: &ui::Node) -> i32 {
+    use std::cmp;
+    let code = query!(block, code).next();
+    let (rect, code) = match (block.render_position(), code.and_then(|v| v.render_position())) {
+        (Some(rect), Some(code)) => (rect, code),
+        _ => return 0,
+    };
+    let right = query!(block, code > line > @text)
+        .filter_map(|v| v.render_position())
+        .map(|v| v.x + v.width)
+        .max()
+        .unwrap_or(code.x);
+    // The code is inset by 10 on either side
+    cmp::max(0, (right - code.x) - (rect.width - 20))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn span(text: &str, style: Style) -> Span {
+        Span {
+            text: text.to_owned(),
+            style: style,
+            link: None,
+        }
+    }
+
+    fn url(text: &str) -> Span {
+        Span {
+            text: text.to_owned(),
+            style: Style::default(),
+            link: Some(Link::Url(text.to_owned())),
+        }
+    }
+
+    fn plain(text: &str) -> Span {
+        span(text, Style::default())
+    }
+
+    #[test]
+    fn inline_styles() {
+        assert_eq!(parse("a **b** *c* ***d*** __e__ ~~f~~ ||g|| `*h*` 2 * 3 * 4 \\*i\\*", &[]), vec![
+            Block::Text(vec![
+                plain("a "),
+                span("b", bold(Style::default())),
+                plain(" "),
+                span("c", italic(Style::default())),
+                plain(" "),
+                span("d", bold_italic(Style::default())),
+                plain(" "),
+                span("e", underline(Style::default())),
+                plain(" "),
+                span("f", strike(Style::default())),
+                plain(" "),
+                span("g", spoiler(Style::default())),
+                plain(" "),
+                span("*h*", mono(Style::default())),
+                plain(" 2 * 3 * 4 *i*"),
+            ]),
+        ]);
+    }
+
+    #[test]
+    fn code_blocks() {
+        assert_eq!(parse("before```rust\nfn main() {}\n```after", &[]), vec![
+            Block::Text(vec![plain("before")]),
+            Block::Code {
+                lang: Some("rust".to_owned()),
+                code: "fn main() {}".to_owned(),
+            },
+            Block::Text(vec![plain("after")]),
+        ]);
+        assert_eq!(parse("```not a language```", &[]), vec![
+            Block::Code {
+                lang: None,
+                code: "not a language".to_owned(),
+            },
+        ]);
+        // Unclosed fences are left as text
+        assert_eq!(parse("```open", &[]), vec![Block::Text(vec![plain("```open")])]);
+    }
+
+    #[test]
+    fn quotes() {
+        assert_eq!(parse("> one\n> **two**\nafter", &[]), vec![
+            Block::Quote(vec![plain("one\n"), span("two", bold(Style::default()))]),
+            Block::Text(vec![plain("after")]),
+        ]);
+        assert_eq!(parse("before\n>>> rest\n> of\nit", &[]), vec![
+            Block::Text(vec![plain("before")]),
+            Block::Quote(vec![plain("rest\n> of\nit")]),
+        ]);
+        // `>` needs a space after it to start a quote
+        assert_eq!(parse(">not", &[]), vec![Block::Text(vec![plain(">not")])]);
+    }
+
+    #[test]
+    fn quoted_spoilers() {
+        assert_eq!(parse("> ||secret|| ~~old~~", &[]), vec![
+            Block::Quote(vec![
+                span("secret", spoiler(Style::default())),
+                plain(" "),
+                span("old", strike(Style::default())),
+            ]),
+        ]);
+    }
+
+    #[test]
+    fn urls_keep_markers() {
+        assert_eq!(parse("https://example.com/foo_bar_baz", &[]), vec![
+            Block::Text(vec![url("https://example.com/foo_bar_baz")]),
+        ]);
+        assert_eq!(parse("see (http://a.com/**x**~~y~~) _then_", &[]), vec![
+            Block::Text(vec![
+                span("see (", Style::default()),
+                url("http://a.com/**x**~~y~~"),
+                span(") ", Style::default()),
+                span("then", italic(Style::default())),
+            ]),
+        ]);
+    }
+
+    #[test]
+    fn markers_next_to_urls() {
+        assert_eq!(parse("*https://example.com/a_b*", &[]), vec![
+            Block::Text(vec![Span {
+                link: Some(Link::Url("https://example.com/a_b".to_owned())),
+                .. span("https://example.com/a_b", italic(Style::default()))
+            }]),
+        ]);
+        // Only urls starting a word are left alone
+        assert_eq!(parse("_xhttps://a_", &[]), vec![
+            Block::Text(vec![span("xhttps://a", italic(Style::default()))]),
+        ]);
+    }
+}
@@ -0,0 +1,253 @@
+//! The server's members and their roles.
+//!
+//! Like the chat model the member list isn't edited directly,
+//! instead the changes reported here are applied to the
+//! `user_list` nodes.
+
+pub mod render;
+
+/// Index of a role in `Members::roles`
+pub type RoleId = usize;
+
+/// Whether a member is around
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Status {
+    Online,
+    Idle,
+    DoNotDisturb,
+    /// Online but shown as offline to everyone else
+    Invisible,
+    Offline,
+}
+
+impl Status {
+    /// The name used for the `status` property on nodes
+    pub fn name(self) -> &'static str {
+        match self {
+            Status::Online => "online",
+            Status::Idle => "idle",
+            Status::DoNotDisturb => "dnd",
+            Status::Invisible => "invisible",
+            Status::Offline => "offline",
+        }
+    }
+
+    /// Looks up a status by the name returned by `name`
+    pub fn from_name(name: &str) -> Option<Status> {
+        match name {
+            "online" => Some(Status::Online),
+            "idle" => Some(Status::Idle),
+            "dnd" => Some(Status::DoNotDisturb),
+            "invisible" => Some(Status::Invisible),
+            "offline" => Some(Status::Offline),
+            _ => None,
+        }
+    }
+
+    /// The name shown to the user
+    pub fn label(self) -> &'static str {
+        match self {
+            Status::Online => "Online",
+            Status::Idle => "Idle",
+            Status::DoNotDisturb => "Do Not Disturb",
+            Status::Invisible => "Invisible",
+            Status::Offline => "Offline",
+        }
+    }
+
+    /// Whether the member is listed as offline
+    pub fn is_offline(self) -> bool {
+        self == Status::Offline || self == Status::Invisible
+    }
+}
+
+/// A role that can be given to members
+#[derive(Clone, Debug)]
+pub struct Role {
+    pub name: String,
+    pub colour: String,
+    /// Whether members with this role are listed in their
+    /// own section
+    pub hoist: bool,
+}
+
+/// A member of the server
+#[derive(Clone, Debug)]
+pub struct Member {
+    pub name: String,
+    /// The member's roles, highest first
+    pub roles: Vec<RoleId>,
+    pub status: Status,
+    /// What the member is playing if anything
+    pub playing: Option<String>,
+    /// Text set by the member to show with their status
+    pub custom_status: Option<String>,
+}
+
+/// A change to the model that the UI needs to reflect
+#[derive(Clone, Debug)]
+pub enum Change {
+    /// The member joined the server
+    Added(String),
+    /// The member's status, activity or roles changed
+    Updated(String),
+    /// The member left the server
+    Removed(String),
+}
+
+/// The members of the server
+pub struct Members {
+    /// Roles ordered from highest to lowest
+    roles: Vec<Role>,
+    members: Vec<Member>,
+    changes: Vec<Change>,
+    /// Set when changes are taken, cleared by `take_modified`
+    modified: bool,
+}
+
+impl Members {
+    pub fn new() -> Members {
+        Members {
+            roles: Vec::new(),
+            members: Vec::new(),
+            changes: Vec::new(),
+            modified: false,
+        }
+    }
+
+    /// Returns the changes since the last call
+    pub fn changes(&mut self) -> ::std::vec::Drain<Change> {
+        self.modified |= !self.changes.is_empty();
+        self.changes.drain(..)
+    }
+
+    /// Returns whether any member changed since the last call
+    pub fn take_modified(&mut self) -> bool {
+        ::std::mem::replace(&mut self.modified, false)
+    }
+
+    /// Adds a role below all existing roles
+    pub fn add_role(&mut self, name: &str, colour: &str, hoist: bool) -> RoleId {
+        self.roles.push(Role {
+            name: name.to_owned(),
+            colour: colour.to_owned(),
+            hoist: hoist,
+        });
+        self.roles.len() - 1
+    }
+
+    /// All roles ordered from highest to lowest
+    pub fn roles(&self) -> &[Role] {
+        &self.roles
+    }
+
+    pub fn role(&self, id: RoleId) -> &Role {
+        &self.roles[id]
+    }
+
+    /// All members in the order they joined
+    pub fn members(&self) -> &[Member] {
+        &self.members
+    }
+
+    /// Looks up a member by name, ignoring case
+    pub fn member(&self, name: &str) -> Option<&Member> {
+        self.members.iter().find(|v| v.name.eq_ignore_ascii_case(name))
+    }
+
+    /// Adds a member to the server
+    pub fn add_member(&mut self, name: &str, roles: &[RoleId], status: Status) {
+        let mut roles = roles.to_vec();
+        roles.sort();
+        self.members.push(Member {
+            name: name.to_owned(),
+            roles: roles,
+            status: status,
+            playing: None,
+            custom_status: None,
+        });
+        self.changes.push(Change::Added(name.to_owned()));
+    }
+
+    /// Removes a member from the server
+    pub fn remove_member(&mut self, name: &str) -> bool {
+        if let Some(pos) = self.members.iter().position(|v| v.name == name) {
+            let member = self.members.remove(pos);
+            self.changes.push(Change::Removed(member.name));
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Changes the member's presence
+    pub fn set_status(&mut self, name: &str, status: Status) -> bool {
+        self.update(name, |m| if m.status == status {
+            false
+        } else {
+            m.status = status;
+            true
+        })
+    }
+
+    /// Replaces the member's roles
+    pub fn set_roles(&mut self, name: &str, roles: &[RoleId]) -> bool {
+        let mut roles = roles.to_vec();
+        roles.sort();
+        self.update(name, |m| if m.roles == roles {
+            false
+        } else {
+            m.roles = roles.clone();
+            true
+        })
+    }
+
+    /// Changes what the member is playing
+    pub fn set_playing(&mut self, name: &str, playing: Option<&str>) -> bool {
+        let playing = playing.map(|v| v.to_owned());
+        self.update(name, |m| if m.playing == playing {
+            false
+        } else {
+            m.playing = playing.clone();
+            true
+        })
+    }
+
+    /// Changes the member's custom status text
+    pub fn set_custom_status(&mut self, name: &str, text: Option<&str>) -> bool {
+        let text = text.map(|v| v.to_owned());
+        self.update(name, |m| if m.custom_status == text {
+            false
+        } else {
+            m.custom_status = text.clone();
+            true
+        })
+    }
+
+    fn update<F>(&mut self, name: &str, f: F) -> bool
+        where F: FnOnce(&mut Member) -> bool
+    {
+        let changed = self.members.iter_mut()
+            .find(|v| v.name == name)
+            .map_or(false, f);
+        if changed {
+            self.changes.push(Change::Updated(name.to_owned()));
+        }
+        changed
+    }
+
+    /// The colour of the member's name, from their highest
+    /// role
+    pub fn colour(&self, member: &Member) -> &str {
+        member.roles.first()
+            .map_or("#ffffff", |&v| &self.roles[v].colour)
+    }
+
+    /// The highest role the member has that is listed in
+    /// its own section
+    pub fn hoisted_role(&self, member: &Member) -> Option<RoleId> {
+        member.roles.iter()
+            .cloned()
+            .find(|&v| self.roles[v].hoist)
+    }
+}
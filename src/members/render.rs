@@ -0,0 +1,182 @@
+//! Builds the nodes in `user_list` from the member model.
+
+use std::collections::{BTreeMap, HashMap};
+
+use ui;
+use super::{Members, Member, Change, RoleId};
+
+/// The section of the list a member is shown under
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+enum Section {
+    /// Online members whose highest hoisted role is this.
+    /// Roles are ordered highest first so sorting by id
+    /// gives the display order.
+    Role(RoleId),
+    /// Online members without a hoisted role
+    Online,
+    Offline,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Entry {
+    Header(Section),
+    User(String),
+}
+
+/// Keeps the nodes in `user_list` in sync with the
+/// member model
+pub struct MemberList {
+    list: ui::Node,
+    headers: HashMap<Section, ui::Node>,
+    users: HashMap<String, ui::Node>,
+    /// The entries in the order they are currently shown
+    entries: Vec<Entry>,
+}
+
+impl MemberList {
+    pub fn new(root: &ui::Node) -> MemberList {
+        MemberList {
+            list: query!(root, user_list).next().unwrap(),
+            headers: HashMap::new(),
+            users: HashMap::new(),
+            entries: Vec::new(),
+        }
+    }
+
+    /// Applies the changes made to the members since
+    /// the last update.
+    ///
+    /// Existing nodes are updated in place and only moved
+    /// if their position in the list changed.
+    pub fn update(&mut self, members: &mut Members) {
+        let changes = members.changes().collect::<Vec<_>>();
+        if changes.is_empty() {
+            return;
+        }
+        for change in changes {
+            match change {
+                Change::Added(name) | Change::Updated(name) => {
+                    if let Some(member) = members.member(&name) {
+                        self.update_user(members, member);
+                    }
+                },
+                Change::Removed(name) => {
+                    if let Some(node) = self.users.remove(&name) {
+                        self.list.remove_child(node);
+                    }
+                    self.entries.retain(|v| *v != Entry::User(name.clone()));
+                },
+            }
+        }
+        self.reorder(members);
+    }
+
+    /// Creates or updates the node for the member
+    fn update_user(&mut self, members: &Members, member: &Member) {
+        let node = self.users.entry(member.name.clone())
+            .or_insert_with(|| node!{
+                user {
+                    icon {
+                        dot
+                    }
+                    name {
+                        @text(member.name.clone())
+                    }
+                }
+            })
+            .clone();
+        node.set_property("col", members.colour(member).to_owned());
+        node.set_property("status", member.status.name().to_owned());
+        node.set_property("offline", member.status.is_offline());
+
+        if let Some(playing) = query!(node, playing).next() {
+            node.remove_child(playing);
+        }
+        if let Some(game) = member.playing.as_ref() {
+            node.add_child(node!{
+                playing {
+                    @text("Playing ")
+                    @text(game.clone())
+                }
+            });
+            if let Some(txt) = query!(node, playing > @text).nth(1) {
+                txt.set_property("game", true);
+            }
+        } else if let Some(text) = member.custom_status.as_ref() {
+            // Games take priority over the custom status
+            node.add_child(node!{
+                playing {
+                    @text(text.clone())
+                }
+            });
+        }
+    }
+
+    /// Moves nodes to match the order the members should be
+    /// listed in and updates the counts in the headers.
+    fn reorder(&mut self, members: &Members) {
+        let mut sections = BTreeMap::new();
+        for member in members.members() {
+            let section = if member.status.is_offline() {
+                Section::Offline
+            } else if let Some(role) = members.hoisted_role(member) {
+                Section::Role(role)
+            } else {
+                Section::Online
+            };
+            sections.entry(section)
+                .or_insert_with(Vec::new)
+                .push(member.name.clone());
+        }
+
+        let mut wanted = Vec::with_capacity(members.members().len() + sections.len());
+        for (&section, names) in &mut sections {
+            names.sort_by_key(|v| v.to_lowercase());
+            let header = self.headers.entry(section)
+                .or_insert_with(|| node!{
+                    section {
+                        @text("")
+                    }
+                });
+            let label = match section {
+                Section::Role(role) => members.role(role).name.to_uppercase(),
+                Section::Online => "ONLINE".to_owned(),
+                Section::Offline => "OFFLINE".to_owned(),
+            };
+            let label = format!("{}\u{2014}{}", label, names.len());
+            if let Some(txt) = query!(header, @text).next() {
+                if txt.text().map_or(true, |v| v != label) {
+                    txt.set_text(label);
+                }
+            }
+            wanted.push(Entry::Header(section));
+            wanted.extend(names.iter().cloned().map(Entry::User));
+        }
+
+        // Everything before the first difference can stay
+        // where it is
+        let same = self.entries.iter()
+            .zip(&wanted)
+            .take_while(|&(a, b)| a == b)
+            .count();
+        for entry in self.entries.split_off(same) {
+            if let Some(node) = self.node(&entry) {
+                self.list.remove_child(node);
+            }
+        }
+        for entry in &wanted[same..] {
+            if let Some(node) = self.node(entry) {
+                self.list.add_child(node);
+            }
+        }
+        self.headers.retain(|k, _| sections.contains_key(k));
+        self.entries = wanted;
+    }
+
+    fn node(&self, entry: &Entry) -> Option<ui::Node> {
+        match *entry {
+            Entry::Header(section) => self.headers.get(&section).cloned(),
+            Entry::User(ref name) => self.users.get(name).cloned(),
+        }
+    }
+}
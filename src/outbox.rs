@@ -0,0 +1,270 @@
+//! Messages waiting to be sent.
+//!
+//! Messages sent from the message box go through the outbox
+//! so nothing is lost while the backend is unreachable. They
+//! are sent in order once connected, and any the server
+//! didn't reply to before the connection dropped are sent
+//! again when it comes back. Messages the server rejects stay
+//! in the outbox as failed until they are retried or deleted.
+//!
+//! The outbox is saved to `$XDG_DATA_HOME/stylish_discord/outbox`
+//! whenever it changes so unsent messages survive restarts.
+//! Unsent messages get new ids when they are loaded so replies
+//! to them are saved as `~` followed by the position of the
+//! message in the file instead.
+
+use std::fs;
+use std::io::{Read, Write};
+use chrono::prelude::*;
+
+use paths;
+use store;
+use backend::{Backend, Request};
+use chat::{Chat, MessageId, SendState};
+
+const FILE: &str = "outbox";
+/// Written as the first line, outboxes from other versions
+/// are ignored
+const HEADER: &str = "stylish_discord outbox 1";
+
+/// A message read from the saved outbox
+struct Record {
+    channel: String,
+    time: DateTime<Utc>,
+    reply_to: Option<Reply>,
+    failed: bool,
+    content: String,
+}
+
+/// The message a saved message replies to
+enum Reply {
+    /// A message the server has
+    Sent(MessageId),
+    /// The position of an unsent message in the file
+    Queued(usize),
+}
+
+struct Entry {
+    /// The message in the chat
+    id: MessageId,
+    /// Set while waiting for the server's reply
+    nonce: Option<u64>,
+    failed: bool,
+}
+
+pub struct Outbox {
+    /// Messages in the order they are sent in
+    entries: Vec<Entry>,
+    next_nonce: u64,
+    dirty: bool,
+}
+
+impl Outbox {
+    /// Loads the saved outbox, adding its messages back to
+    /// the chat
+    pub fn load(chat: &mut Chat) -> Outbox {
+        let mut outbox = Outbox {
+            entries: Vec::new(),
+            next_nonce: 0,
+            dirty: false,
+        };
+        let mut data = String::new();
+        if let Ok(mut f) = fs::File::open(paths::data_dir().join(FILE)) {
+            if let Err(err) = f.read_to_string(&mut data) {
+                println!("Failed to read the outbox: {}", err);
+                return outbox;
+            }
+        }
+        let mut lines = data.lines();
+        if lines.next() != Some(HEADER) {
+            return outbox;
+        }
+        // Kept in place when invalid so that replies can refer
+        // to the others by position
+        let records = lines
+            .map(|line| {
+                let record = parse_record(&store::read_record(line));
+                if record.is_none() {
+                    println!("Skipping invalid outbox record: {:?}", line);
+                }
+                record
+            })
+            .collect::<Vec<_>>();
+
+        // Replies are always written after the message they
+        // reply to, so adding them in time order gives that
+        // message an id first. The file keeps the order they are
+        // sent in, which retrying can change.
+        let mut order = (0..records.len())
+            .filter(|&i| records[i].is_some())
+            .collect::<Vec<_>>();
+        order.sort_by_key(|&i| records[i].as_ref().map(|v| v.time));
+        let mut ids = vec![None; records.len()];
+        for i in order {
+            let record = if let Some(record) = records[i].as_ref() {
+                record
+            } else {
+                continue;
+            };
+            let reply_to = match record.reply_to {
+                Some(Reply::Sent(id)) => Some(id),
+                Some(Reply::Queued(pos)) => ids.get(pos).and_then(|v| *v),
+                None => None,
+            };
+            let state = if record.failed { SendState::Failed } else { SendState::Sending };
+            ids[i] = Some(chat.add_unsent(&record.channel, record.time, &record.content, reply_to, state));
+        }
+        for (record, id) in records.iter().zip(ids) {
+            if let (Some(record), Some(id)) = (record.as_ref(), id) {
+                outbox.entries.push(Entry {
+                    id: id,
+                    nonce: None,
+                    failed: record.failed,
+                });
+            }
+        }
+        outbox
+    }
+
+    /// Queues a message sent with `Chat::send`
+    pub fn push(&mut self, id: MessageId) {
+        self.entries.push(Entry {
+            id: id,
+            nonce: None,
+            failed: false,
+        });
+        self.dirty = true;
+    }
+
+    /// Queues the failed message again, after the messages
+    /// already waiting
+    pub fn retry(&mut self, chat: &mut Chat, id: MessageId) {
+        let idx = if let Some(idx) = self.entries.iter().position(|v| v.id == id && v.failed) {
+            idx
+        } else {
+            return;
+        };
+        let mut entry = self.entries.remove(idx);
+        entry.failed = false;
+        self.entries.push(entry);
+        chat.set_state(id, SendState::Sending);
+        self.dirty = true;
+    }
+
+    /// The server received the message
+    pub fn sent(&mut self, chat: &mut Chat, nonce: u64) {
+        if let Some(idx) = self.entries.iter().position(|v| v.nonce == Some(nonce)) {
+            let entry = self.entries.remove(idx);
+            chat.set_state(entry.id, SendState::Sent);
+            self.dirty = true;
+        }
+    }
+
+    /// The server refused the message
+    pub fn rejected(&mut self, chat: &mut Chat, nonce: u64) {
+        if let Some(entry) = self.entries.iter_mut().find(|v| v.nonce == Some(nonce)) {
+            entry.nonce = None;
+            entry.failed = true;
+            chat.set_state(entry.id, SendState::Failed);
+            self.dirty = true;
+        }
+    }
+
+    /// The connection dropped so the messages waiting for a
+    /// reply will never get one
+    pub fn disconnected(&mut self) {
+        for entry in &mut self.entries {
+            entry.nonce = None;
+        }
+    }
+
+    /// Sends the waiting messages in order while connected
+    /// and saves the outbox if it changed
+    pub fn update(&mut self, chat: &Chat, backend: &mut Backend) {
+        // Deleting an unsent message drops it from the outbox
+        let len = self.entries.len();
+        self.entries.retain(|v| chat.message(v.id).is_some());
+        self.dirty |= self.entries.len() != len;
+
+        if backend.is_connected() {
+            for entry in &mut self.entries {
+                if entry.nonce.is_some() || entry.failed {
+                    continue;
+                }
+                let msg = if let Some(msg) = chat.message(entry.id) {
+                    msg
+                } else {
+                    continue;
+                };
+                let nonce = self.next_nonce;
+                self.next_nonce += 1;
+                entry.nonce = Some(nonce);
+                backend.send(Request::Message {
+                    nonce: nonce,
+                    channel: msg.channel.clone(),
+                    content: msg.content.clone(),
+                });
+            }
+        }
+
+        if self.dirty {
+            self.dirty = false;
+            self.save(chat);
+        }
+    }
+
+    fn save(&self, chat: &Chat) {
+        let dir = paths::data_dir();
+        let tmp = dir.join(format!("{}.tmp", FILE));
+        let saved = self.entries.iter()
+            .filter_map(|v| chat.message(v.id).map(|msg| (v, msg)))
+            .collect::<Vec<_>>();
+        let res = fs::File::create(&tmp)
+            .and_then(|mut f| {
+                writeln!(f, "{}", HEADER)?;
+                for &(entry, msg) in &saved {
+                    let reply_to = match msg.reply_to {
+                        Some(id) => match saved.iter().position(|v| v.1.id == id) {
+                            Some(pos) => format!("~{}", pos),
+                            None => id.to_string(),
+                        },
+                        None => String::new(),
+                    };
+                    store::write_record(&mut f, &[
+                        "message", &msg.channel, &msg.time.to_rfc3339(),
+                        &reply_to,
+                        store::flag(entry.failed),
+                        &msg.content,
+                    ])?;
+                }
+                Ok(())
+            })
+            .and_then(|_| fs::rename(&tmp, dir.join(FILE)));
+        if let Err(err) = res {
+            println!("Failed to save the outbox: {}", err);
+        }
+    }
+}
+
+fn parse_record(fields: &[String]) -> Option<Record> {
+    if fields.len() != 6 || fields[0] != "message" {
+        return None;
+    }
+    let time = if let Some(time) = store::parse_time(&fields[2]) {
+        time
+    } else {
+        return None;
+    };
+    let reply_to = if fields[3].starts_with('~') {
+        fields[3][1..].parse().ok().map(Reply::Queued)
+    } else {
+        fields[3].parse().ok().map(Reply::Sent)
+    };
+    Some(Record {
+        channel: fields[1].clone(),
+        time: time,
+        reply_to: reply_to,
+        failed: fields[4] == "1",
+        content: fields[5].clone(),
+    })
+}
@@ -0,0 +1,35 @@
+//! Locations of files stored outside of the working directory.
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+const APP_DIR: &str = "stylish_discord";
+
+fn xdg_dir(var: &str, fallback: &str) -> PathBuf {
+    let base = env::var_os(var)
+        .map(PathBuf::from)
+        // Relative paths are invalid according to the spec
+        .and_then(|v| if v.is_absolute() { Some(v) } else { None })
+        .or_else(|| env::home_dir().map(|v| v.join(fallback)))
+        .unwrap_or_else(|| PathBuf::from("."));
+    base.join(APP_DIR)
+}
+
+/// The directory for persistent data, `$XDG_DATA_HOME/stylish_discord`.
+///
+/// The directory is created if it doesn't exist.
+pub fn data_dir() -> PathBuf {
+    let dir = xdg_dir("XDG_DATA_HOME", ".local/share");
+    let _ = fs::create_dir_all(&dir);
+    dir
+}
+
+/// The directory for settings, `$XDG_CONFIG_HOME/stylish_discord`.
+///
+/// The directory is created if it doesn't exist.
+pub fn config_dir() -> PathBuf {
+    let dir = xdg_dir("XDG_CONFIG_HOME", ".config");
+    let _ = fs::create_dir_all(&dir);
+    dir
+}
@@ -0,0 +1,321 @@
+//! The emoji picker popup.
+//!
+//! The picker is shown as an overlay so it draws above the
+//! `background` tree and is positioned relative to the node
+//! it was opened for.
+
+use std::cmp;
+use std::fs;
+use std::io::{Read, Write};
+use sdl2::keyboard::Keycode;
+use stylish;
+
+use ui;
+use emoji::{self, Category, Emoji};
+use paths;
+
+/// The number of emoji per row in the grid. Used for
+/// keyboard navigation so must match the styles.
+const COLUMNS: usize = 8;
+/// The heights of a section's header and of a row of its
+/// grid, used to scroll the selection into view
+const HEADER_HEIGHT: i32 = 24;
+const ROW_HEIGHT: i32 = 36;
+/// The height of the picker's scrolling body
+const BODY_HEIGHT: i32 = HEIGHT - 58;
+/// The maximum number of recently used emoji remembered
+const MAX_RECENT: usize = 16;
+
+const WIDTH: i32 = 352;
+const HEIGHT: i32 = 320;
+
+pub struct EmojiPicker {
+    node: Option<ui::Node>,
+    search: String,
+    /// Recently used emoji, most recent first
+    recent: Vec<String>,
+    /// The grid cells in display order with their emoji
+    cells: Vec<(ui::Node, String)>,
+    /// The index of the first cell and the number of cells
+    /// of each section in `cells`
+    sections: Vec<(usize, usize)>,
+    selected: usize,
+}
+
+impl EmojiPicker {
+    pub fn new() -> EmojiPicker {
+        EmojiPicker {
+            node: None,
+            search: String::new(),
+            recent: load_recent(),
+            cells: Vec::new(),
+            sections: Vec::new(),
+            selected: 0,
+        }
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.node.is_some()
+    }
+
+    /// Opens the picker above the anchor rectangle, aligned
+    /// to its right edge.
+    ///
+    /// Returns the search field which should be focused.
+    pub fn open(&mut self, manager: &mut ui::Manager, anchor: stylish::Rect) -> ui::Node {
+        self.close(manager);
+        let node = node!{
+            emoji_picker {
+                search {
+                    content {
+                        @text("")
+                    }
+                }
+                body
+            }
+        };
+        node.set_property("px", anchor.x + anchor.width - WIDTH);
+        node.set_property("py", anchor.y - HEIGHT - 8);
+        manager.push_overlay(node.clone(), true);
+        self.node = Some(node.clone());
+        self.search.clear();
+        self.rebuild();
+        query!(node, search).next().unwrap()
+    }
+
+    pub fn close(&mut self, manager: &mut ui::Manager) {
+        if let Some(node) = self.node.take() {
+            manager.remove_overlay(&node);
+        }
+        self.cells.clear();
+        self.sections.clear();
+    }
+
+    /// Called when the manager dismissed the picker's overlay
+    pub fn dismissed(&mut self) {
+        self.node = None;
+        self.cells.clear();
+        self.sections.clear();
+    }
+
+    /// Adds the character to the search query
+    pub fn input(&mut self, c: char) {
+        self.search.push(c);
+        self.selected = 0;
+        self.rebuild();
+    }
+
+    /// Handles a key press in the search field.
+    ///
+    /// Returns the selected emoji when it is picked using
+    /// the return key.
+    pub fn key(&mut self, key: Keycode) -> Option<String> {
+        let count = self.cells.len();
+        match key {
+            Keycode::Backspace => {
+                if self.search.pop().is_some() {
+                    self.selected = 0;
+                    self.rebuild();
+                }
+                return None;
+            },
+            Keycode::Return => return self.cells.get(self.selected).map(|v| v.1.clone()),
+            _ if count == 0 => return None,
+            Keycode::Left => self.selected = (self.selected + count - 1) % count,
+            Keycode::Right => self.selected = (self.selected + 1) % count,
+            // Up and down stay in the same column, moving
+            // between the sections' separately wrapped grids
+            Keycode::Up => {
+                let (section, row, column) = self.position(self.selected);
+                if row > 0 {
+                    self.selected = self.cell_at(section, row - 1, column);
+                } else if section > 0 {
+                    let rows = rows(self.sections[section - 1].1);
+                    self.selected = self.cell_at(section - 1, rows - 1, column);
+                }
+            },
+            Keycode::Down => {
+                let (section, row, column) = self.position(self.selected);
+                if row + 1 < rows(self.sections[section].1) {
+                    self.selected = self.cell_at(section, row + 1, column);
+                } else if section + 1 < self.sections.len() {
+                    self.selected = self.cell_at(section + 1, 0, column);
+                }
+            },
+            _ => return None,
+        }
+        self.update_selection();
+        None
+    }
+
+    /// Records the emoji as recently used
+    pub fn picked(&mut self, emoji: &str) {
+        self.recent.retain(|v| v != emoji);
+        self.recent.insert(0, emoji.to_owned());
+        self.recent.truncate(MAX_RECENT);
+        save_recent(&self.recent);
+    }
+
+    /// Returns the section, row and column of the cell
+    fn position(&self, idx: usize) -> (usize, usize, usize) {
+        let section = self.sections.iter()
+            .rposition(|v| v.0 <= idx)
+            .unwrap_or(0);
+        let offset = idx - self.sections[section].0;
+        (section, offset / COLUMNS, offset % COLUMNS)
+    }
+
+    /// Returns the cell at the row and column of the section,
+    /// or the row's last cell if it is too short
+    fn cell_at(&self, section: usize, row: usize, column: usize) -> usize {
+        let (start, len) = self.sections[section];
+        start + cmp::min(row * COLUMNS + column, len - 1)
+    }
+
+    fn update_selection(&self) {
+        for (idx, &(ref cell, _)) in self.cells.iter().enumerate() {
+            cell.set_property("selected", idx == self.selected);
+        }
+        self.scroll_to_selection();
+    }
+
+    /// Scrolls the body so the selected cell is visible,
+    /// along with its section's header on the first row
+    fn scroll_to_selection(&self) {
+        let body = if let Some(body) = self.node.as_ref().and_then(|v| query!(v, body).next()) {
+            body
+        } else {
+            return;
+        };
+        if self.cells.is_empty() {
+            body.set_property("scroll", 0);
+            return;
+        }
+        let (section, row, _) = self.position(self.selected);
+        let top = self.sections[..section].iter()
+            .map(|v| HEADER_HEIGHT + rows(v.1) as i32 * ROW_HEIGHT)
+            .sum::<i32>()
+            + HEADER_HEIGHT + row as i32 * ROW_HEIGHT;
+        let first = if row == 0 { top - HEADER_HEIGHT } else { top };
+        let scroll = body.get_property::<i32>("scroll").unwrap_or(0);
+        let scroll = cmp::max(cmp::min(scroll, first), top + ROW_HEIGHT - BODY_HEIGHT);
+        body.set_property("scroll", scroll);
+    }
+
+    fn rebuild(&mut self) {
+        let node = if let Some(node) = self.node.as_ref() {
+            node.clone()
+        } else {
+            return;
+        };
+        if let Some(txt) = query!(node, search > content > @text).next() {
+            txt.set_text(self.search.clone());
+        }
+        if let Some(body) = query!(node, body).next() {
+            node.remove_child(body);
+        }
+        self.cells.clear();
+        self.sections.clear();
+        let body = node!(body);
+
+        let search = self.search.to_lowercase();
+        if search.is_empty() {
+            if !self.recent.is_empty() {
+                let recent = self.recent.clone();
+                let recent = recent.iter()
+                    .map(|v| v.as_str())
+                    .collect::<Vec<_>>();
+                self.add_section(&body, "RECENTLY USED", &recent);
+            }
+            for &category in Category::ALL {
+                let found = unique(emoji::EMOJI.iter().filter(|v| v.category == category));
+                self.add_section(&body, category.name(), &found);
+            }
+        } else {
+            let found = unique(emoji::EMOJI.iter().filter(|v| v.name.contains(&*search)));
+            if found.is_empty() {
+                body.add_child(node!{
+                    empty {
+                        @text("No emoji match your search")
+                    }
+                });
+            } else {
+                self.add_section(&body, "SEARCH RESULTS", &found);
+            }
+        }
+
+        node.add_child(body);
+        if self.selected >= self.cells.len() {
+            self.selected = 0;
+        }
+        self.update_selection();
+    }
+
+    fn add_section(&mut self, body: &ui::Node, name: &str, emoji: &[&str]) {
+        if emoji.is_empty() {
+            return;
+        }
+        self.sections.push((self.cells.len(), emoji.len()));
+        body.add_child(node!{
+            header {
+                @text(name)
+            }
+        });
+        let grid = node!(grid);
+        for &emoji in emoji {
+            let cell = node!{
+                cell {
+                    @text(emoji)
+                }
+            };
+            cell.set_property("emoji", emoji.to_owned());
+            grid.add_child(cell.clone());
+            self.cells.push((cell, emoji.to_owned()));
+        }
+        body.add_child(grid);
+    }
+}
+
+/// Returns the number of grid rows needed for the cells
+fn rows(cells: usize) -> usize {
+    (cells + COLUMNS - 1) / COLUMNS
+}
+
+/// Returns the emoji removing duplicates caused by
+/// multiple shortcodes
+fn unique<'a, I>(iter: I) -> Vec<&'static str>
+    where I: Iterator<Item=&'a Emoji>
+{
+    let mut out = Vec::new();
+    for e in iter {
+        if !out.contains(&e.emoji) {
+            out.push(e.emoji);
+        }
+    }
+    out
+}
+
+fn load_recent() -> Vec<String> {
+    let mut data = String::new();
+    if let Ok(mut f) = fs::File::open(paths::data_dir().join("recent_emoji")) {
+        let _ = f.read_to_string(&mut data);
+    }
+    data.lines()
+        .filter(|v| !v.is_empty())
+        .take(MAX_RECENT)
+        .map(|v| v.to_owned())
+        .collect()
+}
+
+fn save_recent(recent: &[String]) {
+    let res = fs::File::create(paths::data_dir().join("recent_emoji"))
+        .and_then(|mut f| {
+            for emoji in recent {
+                writeln!(f, "{}", emoji)?;
+            }
+            Ok(())
+        });
+    if let Err(err) = res {
+        println!("Failed to save recent emoji: {}", err);
+    }
+}
@@ -0,0 +1,191 @@
+//! The current user's presence.
+//!
+//! Shows the user in `status_bar`, lets them pick their status
+//! and switches them to idle when they stop using the client.
+
+use ui;
+use members::{Members, Status};
+
+/// How long without any input before the user is marked as
+/// idle, in frames (5 minutes)
+pub const IDLE_TIME: f64 = 60.0 * 60.0 * 5.0;
+
+/// The statuses that can be picked, in display order
+const CHOICES: &[Status] = &[
+    Status::Online,
+    Status::Idle,
+    Status::DoNotDisturb,
+    Status::Invisible,
+];
+
+const PICKER_HEIGHT: i32 = 4 * 36 + 56;
+
+pub struct Presence {
+    user: String,
+    bar: ui::Node,
+    picker: Option<ui::Node>,
+    /// Frames since the last keyboard or mouse input
+    inactive: f64,
+    /// Set when the status was changed to idle automatically
+    /// so it can be changed back when the user returns
+    auto_idle: bool,
+}
+
+impl Presence {
+    pub fn new(root: &ui::Node, user: &str) -> Presence {
+        Presence {
+            user: user.to_owned(),
+            bar: query!(root, channel_list > status_bar).next().unwrap(),
+            picker: None,
+            inactive: 0.0,
+            auto_idle: false,
+        }
+    }
+
+    /// Updates the user's avatar, name and status shown
+    /// in the status bar
+    pub fn refresh(&self, members: &Members) {
+        let member = if let Some(member) = members.member(&self.user) {
+            member
+        } else {
+            return;
+        };
+        if query!(self.bar, user).next().is_none() {
+            self.bar.add_child(node!{
+                user {
+                    icon {
+                        dot
+                    }
+                    name {
+                        @text(self.user.clone())
+                    }
+                    status {
+                        @text("")
+                    }
+                }
+            });
+        }
+        let user = query!(self.bar, user).next().unwrap();
+        user.set_property("status", member.status.name().to_owned());
+        if let Some(txt) = query!(user, status > @text).next() {
+            let text = member.custom_status.clone()
+                .unwrap_or_else(|| member.status.label().to_owned());
+            txt.set_text(text);
+        }
+    }
+
+    /// Called for every keyboard or mouse event
+    pub fn activity(&mut self, members: &mut Members) {
+        self.inactive = 0.0;
+        if self.auto_idle {
+            self.auto_idle = false;
+            self.set(members, Status::Online);
+        }
+    }
+
+    /// Marks the user as idle once they have been inactive
+    /// for long enough
+    pub fn update(&mut self, members: &mut Members, delta: f64) {
+        self.inactive += delta;
+        let online = members.member(&self.user)
+            .map_or(false, |v| v.status == Status::Online);
+        if self.inactive >= IDLE_TIME && online && !self.auto_idle {
+            self.auto_idle = true;
+            self.set(members, Status::Idle);
+        }
+    }
+
+    /// Changes the user's status as picked by them
+    pub fn set_status(&mut self, members: &mut Members, status: Status) {
+        self.auto_idle = false;
+        self.set(members, status);
+    }
+
+    fn set(&self, members: &mut Members, status: Status) {
+        if members.set_status(&self.user, status) {
+            self.refresh(members);
+        }
+    }
+
+    /// Changes the user's custom status, empty text clears it
+    pub fn set_custom_status(&self, members: &mut Members, text: &str) {
+        let text = text.trim();
+        let text = if text.is_empty() { None } else { Some(text) };
+        if members.set_custom_status(&self.user, text) {
+            self.refresh(members);
+        }
+    }
+
+    /// Opens the status picker above the status bar.
+    ///
+    /// Returns the custom status field which should be focused.
+    pub fn open_picker(&mut self, manager: &mut ui::Manager, members: &Members) -> Option<ui::Node> {
+        self.close_picker(manager);
+        let rect = if let Some(rect) = self.bar.render_position() {
+            rect
+        } else {
+            return None;
+        };
+        let custom = members.member(&self.user)
+            .and_then(|v| v.custom_status.clone())
+            .unwrap_or_default();
+        let node = node!{
+            status_picker {
+                custom {
+                    content {
+                        @text(custom)
+                    }
+                }
+            }
+        };
+        let current = members.member(&self.user).map(|v| v.status);
+        for &status in CHOICES {
+            let option = node!{
+                option {
+                    dot
+                    @text(status.label())
+                }
+            };
+            option.set_property("status", status.name().to_owned());
+            option.set_property("selected", Some(status) == current);
+            node.add_child(option);
+        }
+        node.set_property("px", rect.x + 8);
+        node.set_property("py", rect.y - PICKER_HEIGHT - 8);
+        manager.push_overlay(node.clone(), true);
+        self.picker = Some(node.clone());
+        query!(node, custom).next()
+    }
+
+    pub fn close_picker(&mut self, manager: &mut ui::Manager) {
+        if let Some(node) = self.picker.take() {
+            manager.remove_overlay(&node);
+        }
+    }
+
+    /// Called when the manager dismissed the picker's overlay
+    pub fn picker_dismissed(&mut self) {
+        self.picker = None;
+    }
+
+    /// Handles a status in the picker being clicked
+    pub fn pick(&mut self, manager: &mut ui::Manager, members: &mut Members, option: &ui::Node) {
+        let status = option.get_property::<String>("status")
+            .and_then(|name| CHOICES.iter().cloned().find(|v| v.name() == name));
+        if let Some(status) = status {
+            self.set_status(members, status);
+        }
+        self.close_picker(manager);
+    }
+
+    /// Saves the text in the custom status field and closes
+    /// the picker, called when return is pressed in the field
+    pub fn save_custom(&mut self, manager: &mut ui::Manager, members: &mut Members) {
+        let text = self.picker.as_ref()
+            .and_then(|v| query!(v, custom > content > @text).next())
+            .and_then(|v| v.text())
+            .unwrap_or_default();
+        self.set_custom_status(members, &text);
+        self.close_picker(manager);
+    }
+}
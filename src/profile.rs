@@ -0,0 +1,163 @@
+//! The user profile popout.
+//!
+//! Shown as an overlay next to the user list entry or author
+//! name it was opened from.
+
+use stylish;
+
+use ui;
+use members::{Members, Member};
+
+const WIDTH: i32 = 250;
+const BANNER_HEIGHT: i32 = 90;
+const NAME_HEIGHT: i32 = 30;
+const ACTIVITY_HEIGHT: i32 = 24;
+const ROW_HEIGHT: i32 = 24;
+const BUTTON_HEIGHT: i32 = 52;
+/// The gap between the popout and the node it was opened for
+const GAP: i32 = 8;
+
+/// The information shown about a user
+#[derive(Clone, Debug)]
+pub struct Profile {
+    pub name: String,
+    pub colour: String,
+    pub roles: Vec<String>,
+    /// What the user is currently doing, e.g. "Playing A game"
+    pub activity: Option<String>,
+}
+
+impl Profile {
+    /// Creates a profile for a user that is only known by
+    /// their name
+    pub fn unknown(name: &str, colour: &str) -> Profile {
+        Profile {
+            name: name.to_owned(),
+            colour: colour.to_owned(),
+            roles: Vec::new(),
+            activity: None,
+        }
+    }
+
+    /// Creates the profile for a member of the server
+    pub fn from_member(members: &Members, member: &Member) -> Profile {
+        Profile {
+            name: member.name.clone(),
+            colour: members.colour(member).to_owned(),
+            roles: member.roles.iter()
+                .map(|&v| members.role(v).name.clone())
+                .collect(),
+            activity: member.playing.as_ref()
+                .map(|v| format!("Playing {}", v)),
+        }
+    }
+
+    /// The height of the popout showing this profile
+    fn height(&self) -> i32 {
+        let mut height = BANNER_HEIGHT + NAME_HEIGHT + BUTTON_HEIGHT;
+        if self.activity.is_some() {
+            height += ACTIVITY_HEIGHT;
+        }
+        if !self.roles.is_empty() {
+            height += ROW_HEIGHT * (self.roles.len() as i32 + 1);
+        }
+        height
+    }
+}
+
+pub struct ProfilePopout {
+    node: Option<ui::Node>,
+    user: Option<String>,
+}
+
+impl ProfilePopout {
+    pub fn new() -> ProfilePopout {
+        ProfilePopout {
+            node: None,
+            user: None,
+        }
+    }
+
+    /// The name of the user the popout is showing
+    pub fn user(&self) -> Option<&str> {
+        self.user.as_ref().map(|v| v.as_str())
+    }
+
+    /// Opens the popout beside the anchor rectangle. It is
+    /// placed on whichever side has more room and moved to
+    /// stay within the window.
+    pub fn open(&mut self, manager: &mut ui::Manager, profile: &Profile, anchor: stylish::Rect) {
+        use std::cmp;
+        self.close(manager);
+        let node = node!{
+            profile_popout {
+                banner {
+                    icon
+                }
+                name {
+                    @text(profile.name.clone())
+                }
+            }
+        };
+        node.set_property("col", profile.colour.clone());
+        if let Some(activity) = profile.activity.as_ref() {
+            node.add_child(node!{
+                activity {
+                    @text(activity.clone())
+                }
+            });
+        }
+        if !profile.roles.is_empty() {
+            node.add_child(node!{
+                header {
+                    @text(if profile.roles.len() == 1 { "ROLE" } else { "ROLES" })
+                }
+            });
+            for role in &profile.roles {
+                let row = node!{
+                    role {
+                        dot
+                        @text(role.clone())
+                    }
+                };
+                row.set_property("col", profile.colour.clone());
+                node.add_child(row);
+            }
+        }
+        node.add_child(node!{
+            message_button {
+                button {
+                    @text("Message")
+                }
+            }
+        });
+
+        let (width, height) = manager.window_size();
+        let popout_height = profile.height();
+        let x = if anchor.x + anchor.width / 2 > width / 2 {
+            anchor.x - WIDTH - GAP
+        } else {
+            anchor.x + anchor.width + GAP
+        };
+        let y = cmp::min(anchor.y, height - popout_height);
+        node.set_property("px", cmp::max(0, cmp::min(x, width - WIDTH)));
+        node.set_property("py", cmp::max(0, y));
+
+        manager.push_overlay(node.clone(), true);
+        self.node = Some(node);
+        self.user = Some(profile.name.clone());
+    }
+
+    pub fn close(&mut self, manager: &mut ui::Manager) {
+        if let Some(node) = self.node.take() {
+            manager.remove_overlay(&node);
+        }
+        self.user = None;
+    }
+
+    /// Called when the manager dismissed the popout's overlay
+    pub fn dismissed(&mut self) {
+        self.node = None;
+        self.user = None;
+    }
+}
@@ -0,0 +1,274 @@
+//! The settings screen.
+//!
+//! Lists the settings in the config file with a button for
+//! each choice. Changes apply as soon as they are clicked and
+//! are written back to the file straight away. Keybindings are
+//! changed by clicking the binding and pressing the new keys.
+
+use std::path::PathBuf;
+use sdl2::keyboard::{Keycode, Mod};
+
+use ui;
+use config::{self, Binding, Config, Theme};
+use chat::time::{Clock, DateOrder};
+
+pub struct Settings {
+    node: Option<ui::Node>,
+    /// The action waiting for a key to be bound to it
+    capturing: Option<String>,
+    /// Whether the last key pressed while capturing was
+    /// rejected for typing text
+    needs_modifier: bool,
+    /// Where the settings are saved, shown at the bottom
+    config_path: PathBuf,
+}
+
+impl Settings {
+    pub fn new() -> Settings {
+        Settings {
+            node: None,
+            capturing: None,
+            needs_modifier: false,
+            config_path: config::path(),
+        }
+    }
+
+    /// Opens the settings screen over the whole window
+    pub fn open(&mut self, manager: &mut ui::Manager, config: &Config) {
+        if self.node.is_some() {
+            return;
+        }
+        let node = node!(settings_screen);
+        manager.push_overlay(node.clone(), false);
+        self.node = Some(node);
+        self.rebuild(config);
+    }
+
+    pub fn close(&mut self, manager: &mut ui::Manager) {
+        if let Some(node) = self.node.take() {
+            manager.remove_overlay(&node);
+        }
+        self.capturing = None;
+        self.needs_modifier = false;
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.node.is_some()
+    }
+
+    /// Called when the manager dismissed the screen's overlay
+    pub fn dismissed(&mut self) {
+        self.node = None;
+        self.capturing = None;
+        self.needs_modifier = false;
+    }
+
+    /// Returns whether the next key pressed should be bound
+    /// instead of handled normally
+    pub fn is_capturing(&self) -> bool {
+        self.capturing.is_some()
+    }
+
+    /// Handles a choice being clicked.
+    ///
+    /// Returns whether the config changed.
+    pub fn pick(&mut self, config: &mut Config, option: &ui::Node) -> bool {
+        let setting = option.get_property::<String>("setting").unwrap_or_default();
+        let value = option.get_property::<String>("value").unwrap_or_default();
+        let old = config.clone();
+        match (setting.as_str(), value.as_str()) {
+            ("theme", "dark") => config.theme = Theme::Dark,
+            ("theme", "light") => config.theme = Theme::Light,
+            ("font_scale", "-") => {
+                let scale = config.font_scale - config::FONT_SCALE_STEP;
+                config.set_font_scale(scale);
+            },
+            ("font_scale", "+") => {
+                let scale = config.font_scale + config::FONT_SCALE_STEP;
+                config.set_font_scale(scale);
+            },
+            ("clock", "auto") => config.timestamps.clock = None,
+            ("clock", "12") => config.timestamps.clock = Some(Clock::Twelve),
+            ("clock", "24") => config.timestamps.clock = Some(Clock::TwentyFour),
+            ("date", "auto") => config.timestamps.date = None,
+            ("date", "dmy") => config.timestamps.date = Some(DateOrder::DayMonthYear),
+            ("date", "mdy") => config.timestamps.date = Some(DateOrder::MonthDayYear),
+            ("date", "iso") => config.timestamps.date = Some(DateOrder::Iso),
+            ("frame_cap", _) => if let Ok(cap) = value.parse() {
+                config.frame_cap = cap;
+            },
+            ("bind", _) => {
+                self.needs_modifier = false;
+                // Clicking again cancels
+                self.capturing = if self.capturing.as_ref() == Some(&value) {
+                    None
+                } else {
+                    Some(value.clone())
+                };
+            },
+            _ => {},
+        }
+        self.rebuild(config);
+        *config != old
+    }
+
+    /// Binds the key to the action being captured. Escape
+    /// cancels without changing the binding and keys that
+    /// type text are refused until Ctrl or Alt is held.
+    ///
+    /// Returns whether the config changed.
+    pub fn capture(&mut self, config: &mut Config, key: Keycode, keymod: Mod) -> bool {
+        if Binding::is_modifier(key) {
+            return false;
+        }
+        let action = if let Some(action) = self.capturing.take() {
+            action
+        } else {
+            return false;
+        };
+        let binding = Binding::from_event(key, keymod);
+        if binding.types_text() {
+            self.capturing = Some(action);
+            self.needs_modifier = true;
+            self.rebuild(config);
+            return false;
+        }
+        self.needs_modifier = false;
+        let changed = if key == Keycode::Escape {
+            false
+        } else {
+            let binding = binding.to_string();
+            config.keybindings.insert(action, binding.clone()) != Some(binding)
+        };
+        self.rebuild(config);
+        changed
+    }
+
+    /// Recreates the panel to show the current settings
+    fn rebuild(&self, config: &Config) {
+        let node = if let Some(node) = self.node.as_ref() {
+            node
+        } else {
+            return;
+        };
+        if let Some(old) = query!(node, panel).next() {
+            node.remove_child(old);
+        }
+        let panel = node!(panel);
+
+        panel.add_child(node!{
+            title {
+                @text("Settings")
+                close {
+                    @text("\u{2715}")
+                }
+            }
+        });
+
+        panel.add_child(section("APPEARANCE"));
+        let theme = match config.theme {
+            Theme::Dark => "dark",
+            Theme::Light => "light",
+        };
+        panel.add_child(setting("Theme", "theme", theme, &config::THEMES.iter()
+            .map(|&v| match v {
+                Theme::Dark => ("dark", "Dark".to_owned()),
+                Theme::Light => ("light", "Light".to_owned()),
+            })
+            .collect::<Vec<_>>()));
+        let label = format!("Font Size ({}%)", (config.font_scale * 100.0).round());
+        panel.add_child(setting(&label, "font_scale", "", &[
+            ("-", "Smaller".to_owned()),
+            ("+", "Larger".to_owned()),
+        ]));
+
+        panel.add_child(section("TIMESTAMPS"));
+        let clock = match config.timestamps.clock {
+            None => "auto",
+            Some(Clock::Twelve) => "12",
+            Some(Clock::TwentyFour) => "24",
+        };
+        panel.add_child(setting("Clock", "clock", clock, &[
+            ("auto", "Locale".to_owned()),
+            ("12", "12 Hour".to_owned()),
+            ("24", "24 Hour".to_owned()),
+        ]));
+        let date = match config.timestamps.date {
+            None => "auto",
+            Some(DateOrder::DayMonthYear) => "dmy",
+            Some(DateOrder::MonthDayYear) => "mdy",
+            Some(DateOrder::Iso) => "iso",
+        };
+        panel.add_child(setting("Date", "date", date, &[
+            ("auto", "Locale".to_owned()),
+            ("dmy", "DD/MM/YYYY".to_owned()),
+            ("mdy", "MM/DD/YYYY".to_owned()),
+            ("iso", "YYYY-MM-DD".to_owned()),
+        ]));
+
+        panel.add_child(section("PERFORMANCE"));
+        let caps = config::FRAME_CAPS.iter()
+            .map(|&v| (v.to_string(), if v == 0 { "Unlimited".to_owned() } else { format!("{} FPS", v) }))
+            .collect::<Vec<_>>();
+        panel.add_child(setting("Frame Cap", "frame_cap", &config.frame_cap.to_string(), &caps.iter()
+            .map(|v| (v.0.as_str(), v.1.clone()))
+            .collect::<Vec<_>>()));
+
+        panel.add_child(section("KEYBINDINGS"));
+        for &(action, label, _) in config::ACTIONS {
+            let capturing = self.capturing.as_ref().map_or(false, |v| v == action);
+            let key = if capturing && self.needs_modifier {
+                "Hold Ctrl or Alt\u{2026}".to_owned()
+            } else if capturing {
+                "Press a key\u{2026}".to_owned()
+            } else {
+                config.binding(action).map_or_else(|| "Unbound".to_owned(), |v| v.to_string())
+            };
+            let row = setting(label, "bind", if capturing { action } else { "" }, &[(action, key)]);
+            row.set_property("keybinding", true);
+            panel.add_child(row);
+        }
+
+        panel.add_child(node!{
+            hint {
+                @text(format!("Saved to {}", self.config_path.display()))
+            }
+        });
+        node.add_child(panel);
+    }
+}
+
+/// Creates the heading for a group of settings
+fn section(title: &str) -> ui::Node {
+    node!{
+        section {
+            @text(title)
+        }
+    }
+}
+
+/// Creates a row for a setting with a button for each of
+/// the values it can have
+fn setting(label: &str, setting: &str, selected: &str, options: &[(&str, String)]) -> ui::Node {
+    let node = node!{
+        setting {
+            label {
+                @text(label)
+            }
+            options
+        }
+    };
+    let row = query!(node, options).next().unwrap();
+    for &(value, ref text) in options {
+        let option = node!{
+            option {
+                @text(text.clone())
+            }
+        };
+        option.set_property("setting", setting.to_owned());
+        option.set_property("value", value.to_owned());
+        option.set_property("selected", value == selected);
+        row.add_child(option);
+    }
+    node
+}
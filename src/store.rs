@@ -0,0 +1,394 @@
+//! The local cache of the server's state.
+//!
+//! Roles, members, direct message channels, muted channels
+//! and the message history are saved to `$XDG_DATA_HOME/stylish_discord/cache`
+//! and loaded on startup so the client has something to show
+//! before the backend connects. Once it does the cache is
+//! reconciled with what the server sent.
+//!
+//! The file is plain text with one tab separated record per
+//! line, tabs, newlines and backslashes in fields are escaped.
+
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::{Read, Write};
+use chrono::prelude::*;
+
+use ui;
+use paths;
+use backend;
+use chat::{Chat, Message, MessageId, MessageKey, Reaction, SendState};
+use members::{Members, RoleId, Status};
+
+const FILE: &str = "cache";
+/// Written as the first line, caches from other versions
+/// are ignored
+const HEADER: &str = "stylish_discord cache 1";
+/// How often the cache is saved when something changed,
+/// in frames
+pub const SAVE_INTERVAL: f64 = 60.0 * 10.0;
+
+/// Everything loaded from the cache
+pub struct Cache {
+    roles: Vec<(String, String, bool)>,
+    /// Members with the names of their roles
+    members: Vec<(String, Vec<String>, Status, Option<String>, Option<String>)>,
+    /// The users that direct message channels were opened with
+    direct_messages: Vec<String>,
+    messages: Vec<Message>,
+    last_read: HashMap<String, MessageId>,
+    muted: Vec<String>,
+    deleted: HashSet<MessageKey>,
+}
+
+impl Cache {
+    /// Loads the cache, returning `None` if there isn't one or
+    /// it can't be read
+    pub fn load() -> Option<Cache> {
+        let mut data = String::new();
+        if let Ok(mut f) = fs::File::open(paths::data_dir().join(FILE)) {
+            if let Err(err) = f.read_to_string(&mut data) {
+                println!("Failed to read the cache: {}", err);
+                return None;
+            }
+        }
+        let mut lines = data.lines();
+        if lines.next() != Some(HEADER) {
+            return None;
+        }
+        let mut cache = Cache {
+            roles: Vec::new(),
+            members: Vec::new(),
+            direct_messages: Vec::new(),
+            messages: Vec::new(),
+            last_read: HashMap::new(),
+            muted: Vec::new(),
+            deleted: HashSet::new(),
+        };
+        for line in lines {
+            if !cache.parse_record(&read_record(line)) {
+                println!("Skipping invalid cache record: {:?}", line);
+            }
+        }
+        Some(cache)
+    }
+
+    fn parse_record(&mut self, fields: &[String]) -> bool {
+        let f = fields.iter().map(|v| v.as_str()).collect::<Vec<_>>();
+        match (f[0], f.len()) {
+            ("role", 4) => {
+                self.roles.push((f[1].to_owned(), f[2].to_owned(), f[3] == "1"));
+            },
+            ("member", 6) => {
+                let status = if let Some(status) = Status::from_name(f[3]) {
+                    status
+                } else {
+                    return false;
+                };
+                let roles = f[2].split(',')
+                    .filter(|v| !v.is_empty())
+                    .map(|v| v.to_owned())
+                    .collect();
+                self.members.push((f[1].to_owned(), roles, status, optional(f[4]), optional(f[5])));
+            },
+            ("direct_message", 2) => self.direct_messages.push(f[1].to_owned()),
+            ("muted", 2) => self.muted.push(f[1].to_owned()),
+            ("message", 10) => {
+                let (id, time) = match (f[1].parse(), parse_time(f[5])) {
+                    (Ok(id), Some(time)) => (id, time),
+                    _ => return false,
+                };
+                self.messages.push(Message {
+                    id: id,
+                    channel: f[2].to_owned(),
+                    author: f[3].to_owned(),
+                    colour: f[4].to_owned(),
+                    time: time,
+                    edited: parse_time(f[6]),
+                    reply_to: f[7].parse().ok(),
+                    pinned: f[8] == "1",
+                    content: f[9].to_owned(),
+                    reactions: Vec::new(),
+                    state: SendState::Sent,
+                });
+            },
+            ("reaction", 4) => {
+                let (emoji, user) = (f[2], f[3]);
+                let msg = f[1].parse::<MessageId>().ok()
+                    .and_then(|id| self.messages.iter_mut().find(|v| v.id == id));
+                let msg = if let Some(msg) = msg {
+                    msg
+                } else {
+                    return false;
+                };
+                if let Some(reaction) = msg.reactions.iter_mut().find(|v| v.emoji == emoji) {
+                    reaction.users.push(user.to_owned());
+                    return true;
+                }
+                msg.reactions.push(Reaction {
+                    emoji: emoji.to_owned(),
+                    users: vec![user.to_owned()],
+                });
+            },
+            ("deleted", 4) => if let Some(time) = parse_time(f[3]) {
+                self.deleted.insert((f[1].to_owned(), f[2].to_owned(), time));
+            } else {
+                return false;
+            },
+            ("read", 3) => if let Ok(id) = f[2].parse() {
+                self.last_read.insert(f[1].to_owned(), id);
+            } else {
+                return false;
+            },
+            _ => return false,
+        }
+        true
+    }
+
+    /// Fills the models with the cached state.
+    ///
+    /// Returns the users that direct message channels were
+    /// open with.
+    pub fn restore(self, chat: &mut Chat, members: &mut Members) -> Vec<String> {
+        for (name, colour, hoist) in self.roles {
+            members.add_role(&name, &colour, hoist);
+        }
+        for (name, roles, status, playing, custom_status) in self.members {
+            let roles = role_ids(members, &roles);
+            members.add_member(&name, &roles, status);
+            members.set_playing(&name, playing.as_ref().map(|v| v.as_str()));
+            members.set_custom_status(&name, custom_status.as_ref().map(|v| v.as_str()));
+        }
+        chat.restore(self.messages, self.last_read, self.deleted);
+        for channel in &self.muted {
+            chat.set_muted(channel, true);
+        }
+        self.direct_messages
+    }
+}
+
+/// Saves the state of the models and the open direct message
+/// channels to the cache
+pub fn save(chat: &Chat, members: &Members, root: &ui::Node) {
+    let dir = paths::data_dir();
+    let tmp = dir.join(format!("{}.tmp", FILE));
+    let res = fs::File::create(&tmp)
+        .and_then(|mut f| {
+            writeln!(f, "{}", HEADER)?;
+            for role in members.roles() {
+                write_record(&mut f, &["role", &role.name, &role.colour, flag(role.hoist)])?;
+            }
+            for member in members.members() {
+                let roles = member.roles.iter()
+                    .map(|&v| members.role(v).name.clone())
+                    .collect::<Vec<_>>()
+                    .join(",");
+                write_record(&mut f, &[
+                    "member", &member.name, &roles, member.status.name(),
+                    member.playing.as_ref().map_or("", |v| v.as_str()),
+                    member.custom_status.as_ref().map_or("", |v| v.as_str()),
+                ])?;
+            }
+            let channels = query!(root, channel_list > channels > channel)
+                .filter(|v| v.get_property::<String>("category").map_or(false, |v| v == "direct"))
+                .filter_map(|v| v.get_property::<String>("name"));
+            for channel in channels {
+                write_record(&mut f, &["direct_message", channel.trim_left_matches('@')])?;
+            }
+            // Messages the server doesn't have yet are saved
+            // in the outbox instead
+            for msg in chat.messages().iter().filter(|v| v.state == SendState::Sent) {
+                write_record(&mut f, &[
+                    "message", &msg.id.to_string(), &msg.channel, &msg.author, &msg.colour,
+                    &msg.time.to_rfc3339(),
+                    &msg.edited.map_or_else(String::new, |v| v.to_rfc3339()),
+                    &msg.reply_to.map_or_else(String::new, |v| v.to_string()),
+                    flag(msg.pinned),
+                    &msg.content,
+                ])?;
+                for reaction in &msg.reactions {
+                    for user in &reaction.users {
+                        write_record(&mut f, &["reaction", &msg.id.to_string(), &reaction.emoji, user])?;
+                    }
+                }
+            }
+            for (channel, id) in chat.last_read() {
+                write_record(&mut f, &["read", channel, &id.to_string()])?;
+            }
+            for channel in chat.muted() {
+                write_record(&mut f, &["muted", channel])?;
+            }
+            for &(ref channel, ref author, time) in chat.deleted() {
+                write_record(&mut f, &["deleted", channel, author, &time.to_rfc3339()])?;
+            }
+            Ok(())
+        })
+        // Replacing the old cache in one go means a crash
+        // while saving can't lose it
+        .and_then(|_| fs::rename(&tmp, dir.join(FILE)));
+    if let Err(err) = res {
+        println!("Failed to save the cache: {}", err);
+    }
+}
+
+/// Brings the models up to date with the server's state
+/// after connecting
+pub fn reconcile(chat: &mut Chat, members: &mut Members, ready: backend::Ready) {
+    for role in &ready.roles {
+        if !members.roles().iter().any(|v| v.name == role.name) {
+            members.add_role(&role.name, &role.colour, role.hoist);
+        }
+    }
+
+    let gone = members.members().iter()
+        .filter(|v| !ready.members.iter().any(|m| m.name == v.name))
+        .map(|v| v.name.clone())
+        .collect::<Vec<_>>();
+    for name in gone {
+        members.remove_member(&name);
+    }
+    for info in &ready.members {
+        let roles = role_ids(members, &info.roles);
+        if members.member(&info.name).is_none() {
+            members.add_member(&info.name, &roles, info.status);
+        } else {
+            members.set_roles(&info.name, &roles);
+            // This client knows its own status better than
+            // the server after being offline
+            if info.name != chat.user {
+                members.set_status(&info.name, info.status);
+            }
+        }
+        members.set_playing(&info.name, info.playing.as_ref().map(|v| v.as_str()));
+    }
+
+    // Messages sent while this client was closed, skipping
+    // any deleted here
+    let server = ready.messages.iter()
+        .map(|v| (v.channel.clone(), v.author.clone(), v.time))
+        .collect::<HashSet<_>>();
+    chat.retain_deleted(&server);
+    let mut known = chat.messages().iter()
+        .map(|v| v.key())
+        .collect::<HashSet<_>>();
+    known.extend(chat.deleted().iter().cloned());
+    for msg in ready.messages {
+        if known.contains(&(msg.channel.clone(), msg.author.clone(), msg.time)) {
+            continue;
+        }
+        let colour = members.member(&msg.author)
+            .map_or("#ffffff", |v| members.colour(v))
+            .to_owned();
+        let id = chat.add_message(&msg.channel, &msg.author, &colour, msg.time, &msg.content);
+        chat.set_pinned(id, msg.pinned);
+        for (emoji, users) in msg.reactions {
+            for user in users {
+                chat.toggle_reaction_as(id, &emoji, &user);
+            }
+        }
+    }
+    chat.sort_by_time();
+}
+
+/// Looks up roles by name, skipping any that don't exist
+fn role_ids(members: &Members, names: &[String]) -> Vec<RoleId> {
+    names.iter()
+        .filter_map(|name| members.roles().iter().position(|v| v.name == *name))
+        .collect()
+}
+
+/// Writes the fields as one line, escaping them
+pub fn write_record<W: Write>(w: &mut W, fields: &[&str]) -> ::std::io::Result<()> {
+    let line = fields.iter()
+        .map(|v| escape(v))
+        .collect::<Vec<_>>()
+        .join("\t");
+    writeln!(w, "{}", line)
+}
+
+/// Splits a line written by `write_record` back into
+/// its fields
+pub fn read_record(line: &str) -> Vec<String> {
+    line.split('\t').map(unescape).collect()
+}
+
+pub fn flag(value: bool) -> &'static str {
+    if value { "1" } else { "0" }
+}
+
+fn optional(value: &str) -> Option<String> {
+    if value.is_empty() {
+        None
+    } else {
+        Some(value.to_owned())
+    }
+}
+
+pub fn parse_time(value: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(value).ok()
+        .map(|v| v.with_timezone(&Utc))
+}
+
+fn escape(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            '\t' => out.push_str("\\t"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+fn unescape(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('t') => out.push('\t'),
+            Some('n') => out.push('\n'),
+            Some('r') => out.push('\r'),
+            Some(c) => out.push(c),
+            None => {},
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escape_round_trip() {
+        for value in &["", "plain", "tab\there", "line\nbreak", "crlf\r\n", "cr\r", "back\\slash", "\\t literal", "trailing\\"] {
+            assert_eq!(unescape(&escape(value)), *value);
+        }
+        assert_eq!(escape("a\tb\nc\\d"), "a\\tb\\nc\\\\d");
+    }
+
+    #[test]
+    fn record_round_trip() {
+        let fields = ["message", "1", "a\tb", "", "multi\nline \\n text\r"];
+        let mut out = Vec::new();
+        write_record(&mut out, &fields).unwrap();
+        let line = String::from_utf8(out).unwrap();
+        assert_eq!(line.matches('\n').count(), 1);
+        // Loading splits the file with `lines`
+        assert_eq!(read_record(line.lines().next().unwrap()), fields);
+    }
+
+    #[test]
+    fn parse_times() {
+        let time = Utc.ymd(2017, 8, 1).and_hms(12, 30, 0);
+        assert_eq!(parse_time(&time.to_rfc3339()), Some(time));
+        assert_eq!(parse_time(""), None);
+    }
+}
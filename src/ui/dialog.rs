@@ -0,0 +1,145 @@
+//! Modal dialogs for asking the user something.
+//!
+//! A dialog is shown as a non-dismissable overlay covering the
+//! window so input can't reach anything else while it is open.
+//! Once answered an `EventType::Dialog` event is fired on the
+//! dialog node using the id it was created with as the method
+//! name, e.g. `("delete_message", EventType::Dialog(true), node)`.
+
+use super::{Manager, Node, NodeEvent, EventType};
+
+/// Describes a dialog to show
+pub struct Dialog {
+    id: String,
+    title: String,
+    message: Option<String>,
+    confirm: String,
+    danger: bool,
+}
+
+impl Dialog {
+    /// Creates a dialog which fires its result event
+    /// using the id
+    pub fn new(id: &str, title: &str) -> Dialog {
+        Dialog {
+            id: id.to_owned(),
+            title: title.to_owned(),
+            message: None,
+            confirm: "Okay".to_owned(),
+            danger: false,
+        }
+    }
+
+    /// Sets the text shown below the title
+    pub fn message(mut self, message: &str) -> Dialog {
+        self.message = Some(message.to_owned());
+        self
+    }
+
+    /// Sets the label of the confirm button
+    pub fn confirm(mut self, label: &str) -> Dialog {
+        self.confirm = label.to_owned();
+        self
+    }
+
+    /// Styles the confirm button as a destructive action
+    pub fn danger(mut self) -> Dialog {
+        self.danger = true;
+        self
+    }
+
+    /// Shows the dialog.
+    ///
+    /// Returns the dialog's node which can be used to store
+    /// properties needed when handling the result. `focus`
+    /// returns the node within it that should be focused.
+    pub fn open(self, manager: &mut Manager) -> Node {
+        let modal = node!{
+            modal {
+                dialog {
+                    body {
+                        title {
+                            @text(self.title)
+                        }
+                    }
+                }
+            }
+        };
+        modal.set_property("dialog_id", self.id);
+        // The body stacks its children so dialogs without a
+        // message don't leave a gap
+        let body = query!(modal, dialog > body).next().unwrap();
+        if let Some(message) = self.message {
+            body.add_child(node!{
+                message {
+                    @text(message)
+                }
+            });
+        }
+        let confirm = node!{
+            button {
+                @text(self.confirm)
+            }
+        };
+        confirm.set_property("confirm", true);
+        confirm.set_property("danger", self.danger);
+        let cancel = node!{
+            button {
+                @text("Cancel")
+            }
+        };
+        cancel.set_property("cancel", true);
+        let buttons = node!(buttons);
+        buttons.add_child(cancel);
+        buttons.add_child(confirm);
+        body.add_child(buttons);
+
+        manager.push_overlay(modal.clone(), false);
+        modal
+    }
+}
+
+/// Returns the dialog's confirm button.
+///
+/// Enter confirms on release and the manager only passes a
+/// release to the node that got the press, so the key that
+/// opened the dialog can't also confirm it.
+pub fn focus(node: &Node) -> Option<Node> {
+    query!(node, dialog > body > buttons > button)
+        .find(|v| v.get_property::<bool>("confirm").unwrap_or(false))
+}
+
+/// Returns the dialog currently shown if any
+pub fn current(manager: &Manager) -> Option<Node> {
+    manager.top_overlay()
+        .and_then(|v| if v.get_property::<String>("dialog_id").is_some() {
+            Some(v)
+        } else {
+            None
+        })
+}
+
+/// Closes the current dialog with the result, used by the
+/// dialog's buttons and the enter key
+pub fn respond(manager: &mut Manager, confirmed: bool) {
+    if let Some(node) = current(manager) {
+        manager.remove_overlay(&node);
+        finish(manager, node, confirmed);
+    }
+}
+
+/// Called when the manager dismissed a dialog using the
+/// escape key, cancelling it
+pub fn dismissed(manager: &mut Manager, node: &Node) {
+    finish(manager, node.clone(), false);
+}
+
+fn finish(manager: &mut Manager, node: Node, confirmed: bool) {
+    if let Some(id) = node.get_property::<String>("dialog_id") {
+        manager.events.push(NodeEvent {
+            node: node,
+            ty: EventType::Dialog(confirmed),
+            value: id,
+        });
+    }
+}
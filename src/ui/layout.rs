@@ -122,6 +122,10 @@ impl LayoutEngine<Info> for Padded {
 pub struct Rows {
     height: i32,
     adjust: bool,
+    /// The gap left between rows
+    spacing: i32,
+    /// How far the rows are scrolled up by
+    offset: i32,
 }
 
 impl Rows {
@@ -129,6 +133,8 @@ impl Rows {
         Rows {
             height: 0,
             adjust: obj.get_value("auto_size").unwrap_or(true),
+            spacing: obj.get_value("spacing").unwrap_or(0),
+            offset: obj.get_value("scroll_offset").unwrap_or(0),
         }
     }
 }
@@ -139,9 +145,12 @@ impl LayoutEngine<Info> for Rows {
         obj: &mut RenderObject<Info>,
         parent: &RenderObject<Info>
     ) {
+        if self.height > 0 {
+            self.height += self.spacing;
+        }
         obj.draw_rect = Rect {
             x: 0,
-            y: self.height,
+            y: self.height - self.offset,
             width: parent.draw_rect.width,
             height: obj.get_value::<i32>("height").unwrap_or(0),
         };
@@ -247,7 +256,8 @@ impl LayoutEngine<Info> for PushBottom {
         obj: &mut RenderObject<Info>,
         parent: &RenderObject<Info>
     ) {
-        obj.draw_rect.y = parent.draw_rect.height - obj.draw_rect.height;
+        obj.draw_rect.y = parent.draw_rect.height - obj.draw_rect.height
+            + obj.get_value::<i32>("scroll_offset").unwrap_or(0);
     }
     fn finalize_layout(
         &mut self,
@@ -256,4 +266,63 @@ impl LayoutEngine<Info> for PushBottom {
     ) {
 
     }
-}
\ No newline at end of file
+}
+
+/// Places children left to right wrapping onto a new
+/// line when they run out of room
+pub struct Flow {
+    spacing: i32,
+    x: i32,
+    y: i32,
+    line_height: i32,
+}
+
+impl Flow {
+    pub fn new(obj: &RenderObject<Info>) -> Flow {
+        Flow {
+            spacing: obj.get_value("spacing").unwrap_or(0),
+            x: 0,
+            y: 0,
+            line_height: 0,
+        }
+    }
+}
+
+impl LayoutEngine<Info> for Flow {
+    fn pre_position_child(
+        &mut self,
+        obj: &mut RenderObject<Info>,
+        _parent: &RenderObject<Info>
+    ) {
+        obj.draw_rect = Rect {
+            x: 0,
+            y: 0,
+            width: obj.get_value::<i32>("width").unwrap_or(0),
+            height: obj.get_value::<i32>("height").unwrap_or(0),
+        };
+    }
+    fn post_position_child(
+        &mut self,
+        obj: &mut RenderObject<Info>,
+        parent: &RenderObject<Info>
+    ) {
+        use std::cmp;
+        let width = obj.draw_rect.width;
+        if self.x > 0 && self.x + width > parent.draw_rect.width {
+            self.x = 0;
+            self.y += self.line_height + self.spacing;
+            self.line_height = 0;
+        }
+        obj.draw_rect.x = self.x;
+        obj.draw_rect.y = self.y;
+        self.x += width + self.spacing;
+        self.line_height = cmp::max(self.line_height, obj.draw_rect.height);
+    }
+    fn finalize_layout(
+        &mut self,
+        obj: &mut RenderObject<Info>,
+        _children: Vec<&mut RenderObject<Info>>
+    ) {
+        obj.draw_rect.height = self.y + self.line_height;
+    }
+}
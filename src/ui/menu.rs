@@ -0,0 +1,349 @@
+//! Context menus shown as overlays at the cursor.
+//!
+//! Nodes declare `on_context_menu` in their style and the
+//! handler for it builds the list of items to show.
+
+use sdl2::keyboard::Keycode;
+use super::{Manager, Node};
+
+const WIDTH: i32 = 200;
+const ITEM_HEIGHT: i32 = 32;
+const SEPARATOR_HEIGHT: i32 = 9;
+
+/// An entry in a context menu
+#[derive(Clone, Debug)]
+pub enum MenuItem {
+    /// A clickable item that produces the action when used
+    Action {
+        label: String,
+        action: String,
+        enabled: bool,
+        /// Styled as a destructive action
+        danger: bool,
+    },
+    /// A line between groups of items
+    Separator,
+    /// An item that opens another menu next to it
+    Submenu {
+        label: String,
+        items: Vec<MenuItem>,
+    },
+}
+
+impl MenuItem {
+    /// Creates an enabled action
+    pub fn action(label: &str, action: &str) -> MenuItem {
+        MenuItem::Action {
+            label: label.to_owned(),
+            action: action.to_owned(),
+            enabled: true,
+            danger: false,
+        }
+    }
+
+    /// Creates an action that can't be used
+    pub fn disabled(label: &str) -> MenuItem {
+        MenuItem::Action {
+            label: label.to_owned(),
+            action: String::new(),
+            enabled: false,
+            danger: false,
+        }
+    }
+
+    /// Creates an action styled as destructive
+    pub fn danger(label: &str, action: &str) -> MenuItem {
+        MenuItem::Action {
+            label: label.to_owned(),
+            action: action.to_owned(),
+            enabled: true,
+            danger: true,
+        }
+    }
+
+    pub fn submenu(label: &str, items: Vec<MenuItem>) -> MenuItem {
+        MenuItem::Submenu {
+            label: label.to_owned(),
+            items: items,
+        }
+    }
+
+    fn selectable(&self) -> bool {
+        match *self {
+            MenuItem::Action { enabled, .. } => enabled,
+            MenuItem::Separator => false,
+            MenuItem::Submenu { .. } => true,
+        }
+    }
+
+    fn height(&self) -> i32 {
+        match *self {
+            MenuItem::Separator => SEPARATOR_HEIGHT,
+            _ => ITEM_HEIGHT,
+        }
+    }
+}
+
+struct Level {
+    node: Node,
+    items: Vec<MenuItem>,
+    rows: Vec<Node>,
+    selected: Option<usize>,
+    x: i32,
+    y: i32,
+}
+
+/// A context menu and any submenus opened from it
+pub struct ContextMenu {
+    target: Option<Node>,
+    levels: Vec<Level>,
+    bounds: (i32, i32),
+}
+
+impl ContextMenu {
+    pub fn new() -> ContextMenu {
+        ContextMenu {
+            target: None,
+            levels: Vec::new(),
+            bounds: (0, 0),
+        }
+    }
+
+    /// The node the menu was opened for
+    pub fn target(&self) -> Option<Node> {
+        self.target.clone()
+    }
+
+    /// Opens the menu at the position, keeping it within
+    /// the bounds.
+    ///
+    /// Returns the menu node which should be focused for
+    /// keyboard navigation.
+    pub fn open(
+        &mut self, manager: &mut Manager,
+        target: Node, items: Vec<MenuItem>,
+        x: i32, y: i32,
+        bounds: (i32, i32),
+    ) -> Node {
+        self.close(manager);
+        self.target = Some(target);
+        self.bounds = bounds;
+        // Keep the first item out from under the cursor
+        self.push_level(manager, items, x + 1, y + 1, None)
+    }
+
+    /// Closes the menu and all submenus
+    pub fn close(&mut self, manager: &mut Manager) {
+        while let Some(level) = self.levels.pop() {
+            manager.remove_overlay(&level.node);
+        }
+    }
+
+    /// Called when the manager dismissed one of the menu's
+    /// overlays. Closes it and anything opened from it.
+    ///
+    /// Returns the menu that should now have focus if any.
+    pub fn dismissed(&mut self, manager: &mut Manager, node: &Node) -> Option<Node> {
+        if let Some(pos) = self.levels.iter().position(|v| v.node.is_same(node)) {
+            while self.levels.len() > pos {
+                let level = self.levels.pop().unwrap();
+                manager.remove_overlay(&level.node);
+            }
+        }
+        self.levels.last().map(|v| v.node.clone())
+    }
+
+    /// Handles a key press while the menu is focused.
+    ///
+    /// Returns the action of the item if one was used.
+    pub fn key(&mut self, manager: &mut Manager, key: Keycode) -> Option<String> {
+        let depth = if self.levels.is_empty() {
+            return None;
+        } else {
+            self.levels.len() - 1
+        };
+        match key {
+            Keycode::Up => self.move_selection(depth, false),
+            Keycode::Down => self.move_selection(depth, true),
+            Keycode::Left if depth > 0 => {
+                let level = self.levels.pop().unwrap();
+                manager.remove_overlay(&level.node);
+            },
+            Keycode::Right | Keycode::Return => {
+                if let Some(idx) = self.levels[depth].selected {
+                    let is_submenu = match self.levels[depth].items[idx] {
+                        MenuItem::Submenu { .. } => true,
+                        _ => false,
+                    };
+                    if key == Keycode::Return || is_submenu {
+                        return self.activate(manager, depth, idx);
+                    }
+                }
+            },
+            _ => {},
+        }
+        None
+    }
+
+    /// Handles an item row being clicked.
+    ///
+    /// Returns the action of the item if it has one.
+    pub fn click(&mut self, manager: &mut Manager, row: &Node) -> Option<String> {
+        let depth = row.get_property::<i32>("level").map(|v| v as usize);
+        let idx = row.get_property::<i32>("index").map(|v| v as usize);
+        if let (Some(depth), Some(idx)) = (depth, idx) {
+            if depth < self.levels.len() {
+                self.select(depth, Some(idx));
+                return self.activate(manager, depth, idx);
+            }
+        }
+        None
+    }
+
+    /// The menu node that should have focus
+    pub fn focus(&self) -> Option<Node> {
+        self.levels.last().map(|v| v.node.clone())
+    }
+
+    fn activate(&mut self, manager: &mut Manager, depth: usize, idx: usize) -> Option<String> {
+        let item = self.levels[depth].items[idx].clone();
+        match item {
+            MenuItem::Action { action, enabled: true, .. } => {
+                self.close(manager);
+                Some(action)
+            },
+            MenuItem::Submenu { items, .. } => {
+                // Replace any submenu already open from this level
+                while self.levels.len() > depth + 1 {
+                    let level = self.levels.pop().unwrap();
+                    manager.remove_overlay(&level.node);
+                }
+                let (x, y) = {
+                    let level = &self.levels[depth];
+                    let offset = level.items[..idx].iter()
+                        .map(|v| v.height())
+                        .sum::<i32>();
+                    (level.x + WIDTH - 4, level.y + offset)
+                };
+                let parent_x = self.levels[depth].x;
+                self.push_level(manager, items, x, y, Some(parent_x));
+                let sub = self.levels.len() - 1;
+                self.move_selection(sub, true);
+                None
+            },
+            _ => None,
+        }
+    }
+
+    fn push_level(
+        &mut self, manager: &mut Manager,
+        items: Vec<MenuItem>,
+        x: i32, y: i32,
+        parent_x: Option<i32>,
+    ) -> Node {
+        let depth = self.levels.len();
+        let node = node!(context_menu);
+        let mut rows = Vec::with_capacity(items.len());
+        for (idx, item) in items.iter().enumerate() {
+            let row = match *item {
+                MenuItem::Separator => node!{
+                    separator {
+                        line
+                    }
+                },
+                MenuItem::Action { ref label, enabled, danger, .. } => {
+                    let row = node!{
+                        item {
+                            @text(label.clone())
+                        }
+                    };
+                    row.set_property("disabled", !enabled);
+                    row.set_property("danger", danger);
+                    row
+                },
+                MenuItem::Submenu { ref label, .. } => {
+                    let row = node!{
+                        item {
+                            @text(label.clone())
+                            arrow {
+                                @text("\u{203A}")
+                            }
+                        }
+                    };
+                    row.set_property("submenu", true);
+                    row
+                },
+            };
+            row.set_property("level", depth as i32);
+            row.set_property("index", idx as i32);
+            node.add_child(row.clone());
+            rows.push(row);
+        }
+
+        // Keep the menu inside the window, submenus flip to
+        // the other side of their parent
+        let height = items.iter().map(|v| v.height()).sum::<i32>();
+        let mut x = x;
+        let mut y = y;
+        if x + WIDTH > self.bounds.0 {
+            x = match parent_x {
+                Some(px) => px - WIDTH + 4,
+                None => self.bounds.0 - WIDTH,
+            };
+        }
+        if y + height > self.bounds.1 {
+            y = self.bounds.1 - height;
+        }
+        let x = if x < 0 { 0 } else { x };
+        let y = if y < 0 { 0 } else { y };
+        node.set_property("px", x);
+        node.set_property("py", y);
+        node.set_property("menu_height", height);
+
+        manager.push_overlay(node.clone(), true);
+        self.levels.push(Level {
+            node: node.clone(),
+            items: items,
+            rows: rows,
+            selected: None,
+            x: x,
+            y: y,
+        });
+        node
+    }
+
+    fn select(&mut self, depth: usize, idx: Option<usize>) {
+        let level = &mut self.levels[depth];
+        level.selected = idx;
+        for (i, row) in level.rows.iter().enumerate() {
+            row.set_property("selected", Some(i) == idx);
+        }
+    }
+
+    /// Moves the selection to the next usable item
+    fn move_selection(&mut self, depth: usize, forward: bool) {
+        let next = {
+            let level = &self.levels[depth];
+            let count = level.items.len();
+            let mut idx = level.selected;
+            let mut next = None;
+            for _ in 0..count {
+                let i = match idx {
+                    None if forward => 0,
+                    None => count - 1,
+                    Some(i) if forward => (i + 1) % count,
+                    Some(i) => (i + count - 1) % count,
+                };
+                if level.items[i].selectable() {
+                    next = Some(i);
+                    break;
+                }
+                idx = Some(i);
+            }
+            next
+        };
+        if next.is_some() {
+            self.select(depth, next);
+        }
+    }
+}
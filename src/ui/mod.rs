@@ -3,10 +3,13 @@
 //! The format is documented in the `format` package.
 
 mod layout;
+pub mod menu;
+pub mod dialog;
+mod tooltip;
 
 use sdl2::keyboard::Keycode;
 use std::rc::Rc;
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 use std::collections::HashMap;
 
 use stylish;
@@ -18,6 +21,13 @@ pub type Node = stylish::Node<stylish_webrender::Info>;
 pub type WeakNode = stylish::WeakNode<stylish_webrender::Info>;
 
 
+/// A node displayed above the main ui tree
+struct Overlay {
+    node: Node,
+    /// Whether clicking outside of the node closes it
+    dismissable: bool,
+}
+
 /// Manages all UI elements.
 pub struct Manager {
     /// The stylish ui manager
@@ -27,29 +37,59 @@ pub struct Manager {
     last_hover: Option<WeakNode>,
 
     style_groups: HashMap<String, Vec<String>>,
+    /// Read by the `scaled` style function
+    font_scale: Rc<Cell<f64>>,
 
     // Used for init/deinit checking
     cycle: bool,
     nodes: Vec<Node>,
 
     events: Vec<NodeEvent>,
+
+    /// Overlays ordered from bottom to top
+    overlays: Vec<Overlay>,
+    /// Set when a mouse press closed an overlay so that the
+    /// matching release doesn't go to the node under it
+    swallow_release: bool,
+    /// The node focused when each held key was pressed so
+    /// that a press which moves the focus doesn't have its
+    /// release handled by the new node too
+    pressed_keys: HashMap<Keycode, WeakNode>,
+
+    tooltip: tooltip::Tooltip,
+    /// The size of the window, used to keep tooltips on screen
+    window_size: (i32, i32),
 }
 
 fn list(params: Vec<stylish::Value>) -> stylish::SResult<stylish::Value> {
     Ok(stylish::Value::Any(Box::new(params)))
 }
 
+/// Scales a font size or line height by the font scale
+/// from the settings
+fn scaled(scale: &Cell<f64>, params: Vec<stylish::Value>) -> stylish::SResult<stylish::Value> {
+    match params.get(0) {
+        Some(&stylish::Value::Integer(v)) => Ok(stylish::Value::Integer((f64::from(v) * scale.get()).round() as i32)),
+        Some(&stylish::Value::Float(v)) => Ok(stylish::Value::Float(v * scale.get())),
+        _ => Err(stylish::Error::CustomStatic("Expected a number")),
+    }
+}
+
 impl Manager {
     pub fn new() -> Manager {
+        let font_scale = Rc::new(Cell::new(1.0));
         Manager {
             manager: Rc::new(RefCell::new({
                 let mut manager = stylish::Manager::new();
                 manager.add_func_raw("list", list);
+                let scale = font_scale.clone();
+                manager.add_func_raw("scaled", move |params| scaled(&scale, params));
                 manager.add_layout_engine("center", |_| Box::new(layout::Center));
                 manager.add_layout_engine("padded", |o| Box::new(layout::Padded::new(o)));
                 manager.add_layout_engine("rows", |o| Box::new(layout::Rows::new(o)));
                 manager.add_layout_engine("clipped", |_| Box::new(layout::Clipped));
                 manager.add_layout_engine("push_bottom", |_| Box::new(layout::PushBottom));
+                manager.add_layout_engine("flow", |o| Box::new(layout::Flow::new(o)));
 
                 manager
             })),
@@ -58,11 +98,19 @@ impl Manager {
             last_hover: None,
 
             style_groups: HashMap::new(),
+            font_scale: font_scale,
 
             cycle: false,
             nodes: Vec::new(),
 
             events: Vec::new(),
+
+            overlays: Vec::new(),
+            swallow_release: false,
+            pressed_keys: HashMap::new(),
+
+            tooltip: tooltip::Tooltip::new(),
+            window_size: (0, 0),
         }
     }
 
@@ -70,6 +118,16 @@ impl Manager {
         self.events.drain(..)
     }
 
+    /// Sets the size of the window the ui is drawn in
+    pub fn resize(&mut self, width: i32, height: i32) {
+        self.window_size = (width, height);
+    }
+
+    /// Returns the size of the window the ui is drawn in
+    pub fn window_size(&self) -> (i32, i32) {
+        self.window_size
+    }
+
     /// Handles text boxes
     pub fn update(&mut self, delta: f64) -> Option<stylish::Rect> {
         let mut text_area = None;
@@ -109,6 +167,20 @@ impl Manager {
             }
         }
 
+        if let Some(target) = self.tooltip.tick(delta) {
+            if let Some(text) = target.get_value::<String>("tooltip") {
+                let node = self.tooltip.create(&text);
+                self.manager.borrow_mut().add_node(node);
+            } else if let Some(method) = target.get_value("on_tooltip") {
+                self.events.push(NodeEvent {
+                    node: target.clone(),
+                    ty: EventType::Tooltip,
+                    value: method,
+                });
+            }
+        }
+        self.tooltip.keep_within(self.window_size.0, self.window_size.1);
+
         let cycle = self.cycle;
         let events = &mut self.events;
         self.nodes.retain(|v| {
@@ -180,6 +252,20 @@ impl Manager {
         self.style_groups.insert(key.to_owned(), group);
     }
 
+    /// Removes the style rules loaded by `load_styles`
+    pub fn unload_styles(&mut self, key: &str) {
+        let manager: &mut stylish::Manager<_> = &mut *self.manager.borrow_mut();
+        for old in self.style_groups.remove(key).into_iter().flat_map(|v| v) {
+            manager.remove_styles(&old);
+        }
+    }
+
+    /// Sets how much `scaled` font sizes are scaled by. The
+    /// styles need reloading for nodes to pick up the change.
+    pub fn set_font_scale(&mut self, scale: f64) {
+        self.font_scale.set(scale);
+    }
+
     /// Renames the named style rules
     pub fn remove_styles(&self, name: &str) {
         self.manager.borrow_mut().remove_styles(name);
@@ -207,11 +293,134 @@ impl Manager {
         self.manager.borrow_mut().remove_node(node);
     }
 
+    /// Shows the node above the main tree and any existing
+    /// overlays. While its the top-most overlay mouse input
+    /// only reaches nodes inside of it.
+    ///
+    /// Dismissable overlays are closed by clicking outside
+    /// of them. Escape closes any overlay.
+    pub fn push_overlay(&mut self, node: Node, dismissable: bool) {
+        self.manager.borrow_mut().add_node(node.clone());
+        self.overlays.push(Overlay {
+            node: node,
+            dismissable: dismissable,
+        });
+    }
+
+    /// Removes the top-most overlay without firing
+    /// its `on_dismiss` event
+    pub fn pop_overlay(&mut self) -> Option<Node> {
+        if let Some(overlay) = self.overlays.pop() {
+            self.manager.borrow_mut().remove_node(overlay.node.clone());
+            Some(overlay.node)
+        } else {
+            None
+        }
+    }
+
+    /// Removes the overlay wherever it is in the stack
+    /// without firing its `on_dismiss` event
+    pub fn remove_overlay(&mut self, node: &Node) -> bool {
+        if let Some(pos) = self.overlays.iter().position(|v| v.node.is_same(node)) {
+            let overlay = self.overlays.remove(pos);
+            self.manager.borrow_mut().remove_node(overlay.node);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Returns the top-most overlay if any
+    pub fn top_overlay(&self) -> Option<Node> {
+        self.overlays.last().map(|v| v.node.clone())
+    }
+
+    /// Returns whether the top-most overlay is modal, keeping
+    /// input inside of it
+    pub fn is_modal(&self) -> bool {
+        self.overlays.last().map_or(false, |v| !v.dismissable)
+    }
+
+    /// Closes the top-most overlay firing its `on_dismiss`
+    /// event. Returns whether there was an overlay to close.
+    pub fn dismiss_overlay(&mut self) -> bool {
+        if let Some(node) = self.pop_overlay() {
+            if let Some(method) = node.get_value("on_dismiss") {
+                self.events.push(NodeEvent {
+                    node: node.clone(),
+                    ty: EventType::Dismiss,
+                    value: method,
+                });
+            }
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Shows the tooltip for the node if the cursor is still
+    /// over it. Used by `on_tooltip` handlers to provide the
+    /// text to display.
+    pub fn show_tooltip(&mut self, target: &Node, text: &str) {
+        if self.tooltip.target().map_or(false, |v| v.is_same(target)) {
+            let node = self.tooltip.create(text);
+            self.manager.borrow_mut().add_node(node);
+        }
+    }
+
+    /// Hides the current tooltip if any
+    pub fn hide_tooltip(&mut self) {
+        if let Some(node) = self.tooltip.hide() {
+            self.manager.borrow_mut().remove_node(node);
+        }
+    }
+
+    /// Returns the nodes at the position that can receive
+    /// mouse input, taking overlays into account
+    fn hit_test(&self, x: i32, y: i32) -> Vec<Node> {
+        let matches = {
+            let manager = self.manager.borrow();
+            manager.query_at(x, y).matches().collect::<Vec<_>>()
+        };
+        let top = if let Some(top) = self.overlays.last() {
+            top
+        } else {
+            return matches;
+        };
+        if contains(&top.node, x, y) {
+            let inside = top.node.query().matches().collect::<Vec<_>>();
+            matches.into_iter()
+                .filter(|v| inside.iter().any(|o| o.is_same(v)))
+                .collect()
+        } else if top.dismissable {
+            let mut covered = Vec::new();
+            for overlay in &self.overlays {
+                covered.extend(overlay.node.query().matches());
+            }
+            matches.into_iter()
+                .filter(|v| !covered.iter().any(|o| o.is_same(v)))
+                .collect()
+        } else {
+            // Modal overlays block everything else
+            Vec::new()
+        }
+    }
+
     /// Handles events targetting the focused element
     pub fn focused_event<E>(&mut self, param: E::Param) -> bool
         where E: Event + 'static,
     {
         if let Some(node) = self.current_focus.as_ref().and_then(|v| v.upgrade()) {
+            if let Some(key) = E::presses_key(&param) {
+                self.pressed_keys.insert(key, node.weak());
+            } else if let Some(key) = E::releases_key(&param) {
+                let pressed = self.pressed_keys.remove(&key)
+                    .and_then(|v| v.upgrade())
+                    .map_or(false, |v| v.is_same(&node));
+                if !pressed {
+                    return false;
+                }
+            }
             if let Some(method) = node.get_value(E::event_key()) {
                 self.events.push(NodeEvent {
                     node: node.clone(),
@@ -228,10 +437,22 @@ impl Manager {
     pub fn mouse_event<E>(&mut self, x: i32, y: i32, param: E::Param) -> bool
         where E: Event + 'static,
     {
-        let matches = {
-            let manager = self.manager.borrow();
-            manager.query_at(x, y).matches()
-        };
+        // Clicking or scrolling hides any tooltip
+        self.hide_tooltip();
+        if E::dismisses_overlays() {
+            self.swallow_release = false;
+            let outside = self.overlays.last()
+                .map_or(false, |v| v.dismissable && !contains(&v.node, x, y));
+            if outside {
+                self.dismiss_overlay();
+                self.swallow_release = true;
+                return true;
+            }
+        } else if E::completes_click() && self.swallow_release {
+            self.swallow_release = false;
+            return true;
+        }
+        let matches = self.hit_test(x, y);
         for node in matches {
             if let Some(method) = node.get_value(E::event_key()) {
                 self.events.push(NodeEvent {
@@ -247,10 +468,14 @@ impl Manager {
 
     /// Handles mouse move events
     pub fn mouse_move(&mut self, x: i32, y: i32) -> bool {
-        let matches = {
-            let manager = self.manager.borrow();
-            manager.query_at(x, y).matches()
-        };
+        let matches = self.hit_test(x, y);
+        let tooltip = matches.iter()
+            .find(|v| v.get_value::<String>("tooltip").is_some()
+                || v.get_value::<String>("on_tooltip").is_some())
+            .cloned();
+        if let Some(old) = self.tooltip.hover(tooltip, x, y) {
+            self.manager.borrow_mut().remove_node(old);
+        }
         for node in matches {
             if node.get_value::<bool>("can_hover").unwrap_or(false) {
                 if self.last_hover.as_ref()
@@ -350,9 +575,15 @@ impl Manager {
             .as_ref()
             .and_then(|v| v.upgrade());
 
-        let matches = manager.query()
-            .matches()
-            .collect::<Vec<_>>();
+        // Modal overlays keep the focus inside of them
+        let matches = match self.overlays.last() {
+            Some(top) if !top.dismissable => top.node.query()
+                .matches()
+                .collect::<Vec<_>>(),
+            _ => manager.query()
+                .matches()
+                .collect::<Vec<_>>(),
+        };
         let mut can_loop = true;
         while can_loop {
             can_loop = false;
@@ -390,6 +621,13 @@ impl Manager {
     }
 }
 
+fn contains(node: &Node, x: i32, y: i32) -> bool {
+    node.render_position().map_or(false, |r| {
+        x >= r.x && x < r.x + r.width
+            && y >= r.y && y < r.y + r.height
+    })
+}
+
 // Events
 
 /// References a button on the mouse
@@ -446,6 +684,8 @@ pub struct MouseScroll {
     pub y: i32,
     /// The amount the mouse wheel was scrolled by
     pub scroll_amount: i32,
+    /// The amount the mouse wheel was scrolled by horizontally
+    pub scroll_amount_x: i32,
 }
 
 /// Parameter to events that invoke a character being
@@ -492,6 +732,29 @@ impl Event for MouseDownEvent {
     fn event_key() -> &'static str {
         "on_mouse_down"
     }
+
+    fn dismisses_overlays() -> bool {
+        true
+    }
+}
+
+/// Event that is fired when a node is right clicked
+pub enum ContextMenuEvent {}
+
+impl Event for ContextMenuEvent {
+    type Param = MouseClick;
+
+    fn into_node_event(p: Self::Param) -> EventType {
+        EventType::ContextMenu(p)
+    }
+
+    fn event_key() -> &'static str {
+        "on_context_menu"
+    }
+
+    fn dismisses_overlays() -> bool {
+        true
+    }
 }
 
 /// Event that is fired when a mouse button is released
@@ -507,6 +770,10 @@ impl Event for MouseUpEvent {
     fn event_key() -> &'static str {
         "on_mouse_up"
     }
+
+    fn completes_click() -> bool {
+        true
+    }
 }
 
 /// Event that is fired when the mouse wheel is scrolled
@@ -552,6 +819,10 @@ impl Event for KeyDownEvent {
     fn event_key() -> &'static str {
         "on_key_down"
     }
+
+    fn presses_key(p: &Self::Param) -> Option<Keycode> {
+        Some(p.input)
+    }
 }
 
 /// Event that is fired when a key is released
@@ -567,6 +838,10 @@ impl Event for KeyUpEvent {
     fn event_key() -> &'static str {
         "on_key_up"
     }
+
+    fn releases_key(p: &Self::Param) -> Option<Keycode> {
+        Some(p.input)
+    }
 }
 
 /// An event that can be handled by an element
@@ -576,6 +851,29 @@ pub trait Event: Sized {
 
     fn into_node_event(p: Self::Param) -> EventType;
     fn event_key() -> &'static str;
+
+    /// Whether the event closes dismissable overlays when
+    /// it happens outside of them
+    fn dismisses_overlays() -> bool {
+        false
+    }
+
+    /// Whether the event finishes a click started by an
+    /// event that `dismisses_overlays`
+    fn completes_click() -> bool {
+        false
+    }
+
+    /// The key the event starts pressing on the focused node
+    fn presses_key(_p: &Self::Param) -> Option<Keycode> {
+        None
+    }
+
+    /// The key the event releases. It is only handled by the
+    /// node that was focused when the key was pressed.
+    fn releases_key(_p: &Self::Param) -> Option<Keycode> {
+        None
+    }
 }
 
 #[derive(Clone, Copy, Debug)]
@@ -592,6 +890,11 @@ pub enum EventType {
     MouseUp(MouseClick),
     MouseDown(MouseClick),
     MouseMove(MouseMove),
+    ContextMenu(MouseClick),
+    Dismiss,
+    Tooltip,
+    /// The result of a dialog, true if confirmed
+    Dialog(bool),
 }
 
 #[derive(Clone)]
@@ -0,0 +1,140 @@
+//! Tooltips shown after resting the cursor over a node.
+//!
+//! Nodes opt in with a `tooltip` style value containing the
+//! text to show, or with an `on_tooltip` handler which should
+//! provide the text via `Manager::show_tooltip` when fired.
+
+use super::{Node, WeakNode};
+
+/// How long the cursor has to rest over a node before its
+/// tooltip is shown, in frames
+pub const DELAY: f64 = 30.0;
+
+/// The offset of the tooltip from the cursor
+const OFFSET_X: i32 = 12;
+const OFFSET_Y: i32 = 20;
+
+pub(super) struct Tooltip {
+    /// The node under the cursor that has a tooltip
+    target: Option<WeakNode>,
+    x: i32,
+    y: i32,
+    /// Frames left until the tooltip is shown. `None` once
+    /// shown or after being hidden by a click or scroll so
+    /// it doesn't reappear until the cursor leaves.
+    timer: Option<f64>,
+    node: Option<Node>,
+}
+
+impl Tooltip {
+    pub(super) fn new() -> Tooltip {
+        Tooltip {
+            target: None,
+            x: 0,
+            y: 0,
+            timer: None,
+            node: None,
+        }
+    }
+
+    pub(super) fn target(&self) -> Option<Node> {
+        self.target.as_ref().and_then(|v| v.upgrade())
+    }
+
+    /// Updates the node the cursor is over, restarting the
+    /// delay if it changed.
+    ///
+    /// Returns the old tooltip node if it needs removing.
+    pub(super) fn hover(&mut self, target: Option<Node>, x: i32, y: i32) -> Option<Node> {
+        let same = match (self.target(), target.as_ref()) {
+            (Some(a), Some(b)) => a.is_same(b),
+            (None, None) => true,
+            _ => false,
+        };
+        if same {
+            // Follow the cursor until the tooltip appears
+            if self.node.is_none() {
+                self.x = x;
+                self.y = y;
+            }
+            return None;
+        }
+        self.timer = target.as_ref().map(|_| DELAY);
+        self.target = target.map(|v| v.weak());
+        self.x = x;
+        self.y = y;
+        self.node.take()
+    }
+
+    /// Hides the tooltip until the cursor moves to
+    /// another node.
+    ///
+    /// Returns the tooltip node if it needs removing.
+    pub(super) fn hide(&mut self) -> Option<Node> {
+        self.timer = None;
+        self.node.take()
+    }
+
+    /// Counts down the delay, returning the target once
+    /// its tooltip should be shown
+    pub(super) fn tick(&mut self, delta: f64) -> Option<Node> {
+        let timer = if let Some(timer) = self.timer {
+            timer - delta
+        } else {
+            return None;
+        };
+        if timer > 0.0 {
+            self.timer = Some(timer);
+            return None;
+        }
+        self.timer = None;
+        self.target()
+    }
+
+    /// Creates the tooltip node for the target. The caller
+    /// must add it to the ui.
+    pub(super) fn create(&mut self, text: &str) -> Node {
+        let node = node!{
+            tooltip {
+                @text(text.to_owned())
+            }
+        };
+        node.set_property("px", self.x + OFFSET_X);
+        node.set_property("py", self.y + OFFSET_Y);
+        self.node = Some(node.clone());
+        node
+    }
+
+    /// Moves the tooltip back inside the window once its
+    /// size is known, flipping it above the cursor if there
+    /// isn't room below.
+    pub(super) fn keep_within(&self, width: i32, height: i32) {
+        use std::cmp;
+        let node = if let Some(node) = self.node.as_ref() {
+            node
+        } else {
+            return;
+        };
+        let rect = if let Some(rect) = node.render_position() {
+            rect
+        } else {
+            return;
+        };
+        let mut x = self.x + OFFSET_X;
+        let mut y = self.y + OFFSET_Y;
+        if x + rect.width > width {
+            x = width - rect.width;
+        }
+        if y + rect.height > height {
+            y = self.y - rect.height - 4;
+        }
+        let x = cmp::max(0, x);
+        let y = cmp::max(0, y);
+        if node.get_property::<i32>("px") != Some(x) {
+            node.set_property("px", x);
+        }
+        if node.get_property::<i32>("py") != Some(y) {
+            node.set_property("py", y);
+        }
+    }
+}
@@ -0,0 +1,133 @@
+//! Audio for voice channels.
+//!
+//! There isn't a voice server yet so the audio that would be
+//! sent is played straight back instead. The "microphone" is
+//! a WAV file named by `STYLISH_DISCORD_VOICE_FILE`, or
+//! silence when it isn't set so joining a channel doesn't
+//! make any noise.
+
+use std::env;
+use std::path::Path;
+
+use sdl2::AudioSubsystem;
+use sdl2::audio::{AudioCallback, AudioCVT, AudioDevice, AudioFormat, AudioSpecDesired, AudioSpecWAV};
+
+/// The environment variable naming the WAV file to use as
+/// the microphone
+const FILE_VAR: &str = "STYLISH_DISCORD_VOICE_FILE";
+const FREQ: i32 = 44_100;
+/// The level above which the user counts as speaking
+const SPEAKING_LEVEL: f32 = 0.02;
+
+/// Plays the captured audio back to the user
+struct Loopback {
+    /// The captured audio, looped forever. Empty
+    /// when there is no microphone.
+    samples: Vec<f32>,
+    pos: usize,
+    muted: bool,
+    deafened: bool,
+    /// The loudest sample sent in the last buffer
+    level: f32,
+}
+
+impl AudioCallback for Loopback {
+    type Channel = f32;
+
+    fn callback(&mut self, out: &mut [f32]) {
+        let mut level = 0.0f32;
+        for v in out.iter_mut() {
+            let sample = if self.samples.is_empty() {
+                0.0
+            } else {
+                let sample = self.samples[self.pos];
+                self.pos = (self.pos + 1) % self.samples.len();
+                sample
+            };
+            // Muting stops sending but the microphone keeps
+            // running so unmuting carries on from the same place
+            let sample = if self.muted { 0.0 } else { sample };
+            level = level.max(sample.abs());
+            *v = if self.deafened { 0.0 } else { sample };
+        }
+        self.level = level;
+    }
+}
+
+/// The audio device used while connected to a voice channel
+pub struct Audio {
+    device: AudioDevice<Loopback>,
+}
+
+impl Audio {
+    /// Opens the playback device and starts looping the
+    /// captured audio back
+    pub fn open(audio: &AudioSubsystem) -> Result<Audio, String> {
+        let desired = AudioSpecDesired {
+            freq: Some(FREQ),
+            channels: Some(1),
+            samples: None,
+        };
+        let device = audio.open_playback(None, &desired, |spec| Loopback {
+            samples: capture(spec.freq),
+            pos: 0,
+            muted: false,
+            deafened: false,
+            level: 0.0,
+        })?;
+        device.resume();
+        Ok(Audio {
+            device: device,
+        })
+    }
+
+    pub fn set_muted(&mut self, muted: bool) {
+        self.device.lock().muted = muted;
+    }
+
+    pub fn set_deafened(&mut self, deafened: bool) {
+        self.device.lock().deafened = deafened;
+    }
+
+    /// Returns whether the audio being sent is loud enough to
+    /// count as speaking
+    pub fn speaking(&mut self) -> bool {
+        self.device.lock().level > SPEAKING_LEVEL
+    }
+}
+
+/// Returns the audio to use as the microphone's input at the
+/// sample rate
+fn capture(freq: i32) -> Vec<f32> {
+    let path = if let Some(path) = env::var_os(FILE_VAR) {
+        path
+    } else {
+        return Vec::new();
+    };
+    match load_wav(Path::new(&path), freq) {
+        Ok(samples) => samples,
+        Err(err) => {
+            println!("Failed to load {:?}: {}", path, err);
+            Vec::new()
+        },
+    }
+}
+
+/// Loads the WAV file converted to mono floats
+fn load_wav(path: &Path, freq: i32) -> Result<Vec<f32>, String> {
+    let wav = AudioSpecWAV::load_wav(path)?;
+    let cvt = AudioCVT::new(
+        wav.format, wav.channels, wav.freq,
+        AudioFormat::F32LSB, 1, freq,
+    )?;
+    let data = cvt.convert(wav.buffer().to_vec());
+    Ok(data.chunks(4)
+        .filter(|v| v.len() == 4)
+        .map(|v| f32::from_bits(
+            u32::from(v[0])
+                | u32::from(v[1]) << 8
+                | u32::from(v[2]) << 16
+                | u32::from(v[3]) << 24
+        ))
+        .collect())
+}
@@ -0,0 +1,256 @@
+//! Voice channels.
+//!
+//! Users connected to a voice channel are listed beneath it
+//! in the channel list. While this client is connected the
+//! voice panel in `status_bar` shows which channel it is in
+//! and has the mute, deafen and disconnect buttons.
+
+pub mod audio;
+
+use std::collections::{HashMap, HashSet};
+
+use sdl2::AudioSubsystem;
+
+use ui;
+
+/// How long the user is still shown as speaking after they
+/// go quiet, in frames. Stops the indicator flickering
+/// between words.
+const SPEAKING_HOLD: f64 = 15.0;
+
+pub struct Voice {
+    user: String,
+    audio: Option<AudioSubsystem>,
+    /// The audio device, open while connected
+    device: Option<audio::Audio>,
+    channel_list: ui::Node,
+    channels: ui::Node,
+    bar: ui::Node,
+    panel: Option<ui::Node>,
+
+    /// The voice channel this client is connected to
+    connected: Option<String>,
+    /// The users in each voice channel in the order they
+    /// joined
+    users: HashMap<String, Vec<String>>,
+    speaking: HashSet<String>,
+    /// Frames left that this client's user is shown as
+    /// speaking for
+    hold: f64,
+    muted: bool,
+    deafened: bool,
+    dirty: bool,
+}
+
+impl Voice {
+    pub fn new(root: &ui::Node, user: &str, audio: Option<AudioSubsystem>) -> Voice {
+        Voice {
+            user: user.to_owned(),
+            audio: audio,
+            device: None,
+            channel_list: query!(root, channel_list).next().unwrap(),
+            channels: query!(root, channel_list > channels).next().unwrap(),
+            bar: query!(root, channel_list > status_bar).next().unwrap(),
+            panel: None,
+
+            connected: None,
+            users: HashMap::new(),
+            speaking: HashSet::new(),
+            hold: 0.0,
+            muted: false,
+            deafened: false,
+            dirty: true,
+        }
+    }
+
+    /// Adds the user to the voice channel, moving them out
+    /// of any other voice channel
+    pub fn user_joined(&mut self, channel: &str, user: &str) {
+        self.remove_user(user);
+        self.users.entry(channel.to_owned())
+            .or_insert_with(Vec::new)
+            .push(user.to_owned());
+        self.dirty = true;
+    }
+
+    /// Removes the user from whichever voice channel they
+    /// are in
+    pub fn user_left(&mut self, user: &str) {
+        self.remove_user(user);
+        self.speaking.remove(user);
+        self.dirty = true;
+    }
+
+    fn remove_user(&mut self, user: &str) {
+        for users in self.users.values_mut() {
+            users.retain(|v| v != user);
+        }
+    }
+
+    pub fn set_speaking(&mut self, user: &str, speaking: bool) {
+        let changed = if speaking {
+            self.speaking.insert(user.to_owned())
+        } else {
+            self.speaking.remove(user)
+        };
+        self.dirty |= changed;
+    }
+
+    /// Connects this client to the voice channel
+    pub fn join(&mut self, channel: &str) {
+        if self.connected.as_ref().map_or(false, |v| v == channel) {
+            return;
+        }
+        self.disconnect();
+        if let Some(audio) = self.audio.as_ref() {
+            match audio::Audio::open(audio) {
+                Ok(mut device) => {
+                    device.set_muted(self.muted || self.deafened);
+                    device.set_deafened(self.deafened);
+                    self.device = Some(device);
+                },
+                Err(err) => println!("Failed to open audio device: {}", err),
+            }
+        }
+        self.connected = Some(channel.to_owned());
+        let user = self.user.clone();
+        self.user_joined(channel, &user);
+        self.open_panel(channel);
+    }
+
+    pub fn disconnect(&mut self) {
+        if self.connected.take().is_none() {
+            return;
+        }
+        self.device = None;
+        self.hold = 0.0;
+        let user = self.user.clone();
+        self.user_left(&user);
+        if let Some(panel) = self.panel.take() {
+            self.bar.remove_child(panel);
+        }
+        self.bar.set_property("voice", false);
+        self.channel_list.set_property("voice", false);
+    }
+
+    pub fn toggle_mute(&mut self) {
+        if self.deafened {
+            // Unmuting while deafened undeafens as well like
+            // the official client
+            self.deafened = false;
+            self.muted = false;
+        } else {
+            self.muted = !self.muted;
+        }
+        self.apply_audio_state();
+    }
+
+    pub fn toggle_deafen(&mut self) {
+        self.deafened = !self.deafened;
+        self.apply_audio_state();
+    }
+
+    fn apply_audio_state(&mut self) {
+        let (muted, deafened) = (self.muted || self.deafened, self.deafened);
+        if let Some(device) = self.device.as_mut() {
+            device.set_muted(muted);
+            device.set_deafened(deafened);
+        }
+        self.dirty = true;
+    }
+
+    /// Handles a button in the voice panel being clicked
+    pub fn button(&mut self, node: &ui::Node) {
+        match node.get_property::<String>("action").as_ref().map(|v| v.as_str()) {
+            Some("mute") => self.toggle_mute(),
+            Some("deafen") => self.toggle_deafen(),
+            Some("disconnect") => self.disconnect(),
+            _ => {},
+        }
+    }
+
+    fn open_panel(&mut self, channel: &str) {
+        if let Some(panel) = self.panel.take() {
+            self.bar.remove_child(panel);
+        }
+        let panel = node!{
+            voice_panel {
+                state {
+                    @text("Voice Connected")
+                }
+                location {
+                    @text(channel.to_owned())
+                }
+            }
+        };
+        for &(action, label) in &[("mute", "M"), ("deafen", "D"), ("disconnect", "\u{2715}")] {
+            let button = node!{
+                voice_button {
+                    @text(label)
+                }
+            };
+            button.set_property("action", action.to_owned());
+            panel.add_child(button);
+        }
+        self.bar.add_child(panel.clone());
+        self.bar.set_property("voice", true);
+        self.channel_list.set_property("voice", true);
+        self.panel = Some(panel);
+        self.dirty = true;
+    }
+
+    /// Updates whether this client's user is speaking and
+    /// the nodes showing who is in each voice channel
+    pub fn update(&mut self, delta: f64) {
+        let speaking = self.device.as_mut().map_or(false, |v| v.speaking());
+        if speaking {
+            self.hold = SPEAKING_HOLD;
+        } else {
+            self.hold -= delta;
+        }
+        let (user, speaking) = (self.user.clone(), self.connected.is_some() && self.hold > 0.0);
+        self.set_speaking(&user, speaking);
+
+        if !self.dirty {
+            return;
+        }
+        self.dirty = false;
+        for channel in query!(self.channels, channel).collect::<Vec<_>>() {
+            let name = match (channel.get_property::<bool>("voice"), channel.get_property::<String>("name")) {
+                (Some(true), Some(name)) => name,
+                _ => continue,
+            };
+            for node in query!(channel, voice_user).collect::<Vec<_>>() {
+                channel.remove_child(node);
+            }
+            let users = self.users.get(&name).map_or(&[][..], |v| &v[..]);
+            for (row, user) in users.iter().enumerate() {
+                let node = node!{
+                    voice_user {
+                        icon
+                        @text(user.clone())
+                    }
+                };
+                node.set_property("row", row as i32);
+                node.set_property("speaking", self.speaking.contains(user));
+                if *user == self.user {
+                    node.set_property("muted", self.muted || self.deafened);
+                    node.set_property("deafened", self.deafened);
+                }
+                channel.add_child(node);
+            }
+            channel.set_property("users", users.len() as i32);
+            channel.set_property("connected", self.connected.as_ref() == Some(&name));
+        }
+        if let Some(panel) = self.panel.as_ref() {
+            for button in query!(panel, voice_button) {
+                let active = match button.get_property::<String>("action").as_ref().map(|v| v.as_str()) {
+                    Some("mute") => self.muted || self.deafened,
+                    Some("deafen") => self.deafened,
+                    _ => false,
+                };
+                button.set_property("active", active);
+            }
+        }
+    }
+}